@@ -0,0 +1,66 @@
+// Compares lookups in the sorted and the Eytzinger layout, with and without
+// the prefix index, on a synthetic database, `cargo bench`.
+
+use std::hint::black_box;
+use std::net::{ IpAddr, Ipv4Addr };
+use std::time::Instant;
+
+use ip2location::{ Builder, IpDb };
+
+
+const RANGES: u32 = 1_000_000;
+const LOOKUPS: usize = 2_000_000;
+
+fn build(csv: &str, compact: bool, eytzinger: bool, prefix_index: bool) -> IpDb {
+    let mut builder = Builder::new();
+    builder.add_v4(csv.as_bytes()).unwrap();
+    builder.set_compact(compact);
+    builder.set_eytzinger(eytzinger);
+    builder.set_prefix_index(prefix_index);
+    let mut buf = Vec::new();
+    builder.write(&mut buf).unwrap();
+    IpDb::from_bytes(buf).unwrap()
+}
+
+/// xorshift32, the same addresses on every run.
+fn addrs(n: usize) -> Vec<IpAddr> {
+    let mut x = 2463534242u32;
+    (0..n).map(|_| {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        IpAddr::V4(Ipv4Addr::from(x))
+    }).collect()
+}
+
+fn bench<F: FnMut()>(name: &str, n: usize, mut f: F) {
+    f();
+    let now = Instant::now();
+    f();
+    let elapsed = now.elapsed();
+    println!("{:<20} {:>8.1} ns/lookup", name, elapsed.as_nanos() as f64 / n as f64);
+}
+
+fn main() {
+    // NOTE: 相邻段的国家不同，避免被构建器合并。
+    let mut csv = String::new();
+    let step = u32::MAX / RANGES;
+    for i in 0..RANGES {
+        let start = i * step;
+        let country = ["US", "CN", "JP", "DE"][i as usize % 4];
+        csv.push_str(&format!("\"{}\",\"{}\",\"{}\",\"-\"\n", start, start + step / 2, country));
+    }
+
+    let addrs = addrs(LOOKUPS);
+    for &(compact, layout) in [(false, "records"), (true, "compact")].iter() {
+        let searches = [(false, false, "binary"), (true, false, "eytzinger"), (false, true, "prefix index")];
+        for &(eytzinger, prefix_index, search) in searches.iter() {
+            let db = build(&csv, compact, eytzinger, prefix_index);
+            bench(&format!("{} {}", layout, search), addrs.len(), || {
+                for addr in addrs.iter() {
+                    black_box(db.query(black_box(addr)));
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+use std::net::{ IpAddr, Ipv4Addr, Ipv6Addr };
+
+
+// NOTE: 数据库里的 IPv4/IPv6 地址分别以 u32/u128 (Little Endian) 存储。
+pub trait Address: Copy + Ord + fmt::Debug + 'static {
+    /// Address width in bytes.
+    const SIZE: usize;
+    /// Address width in bits.
+    const BITS: u32;
+
+    fn from_le_slice(bytes: &[u8]) -> Self;
+    fn write_le(self, buf: &mut Vec<u8>);
+    fn to_ip(self) -> IpAddr;
+    /// `None` if `addr` belongs to the other family.
+    fn from_ip(addr: IpAddr) -> Option<Self>;
+    fn to_u128(self) -> u128;
+    /// Truncates to the address width.
+    fn from_u128(n: u128) -> Self;
+}
+
+impl Address for u32 {
+    const SIZE: usize = 4;
+    const BITS: u32 = 32;
+
+    #[inline]
+    fn from_le_slice(bytes: &[u8]) -> Self {
+        u32::from_le_bytes([ bytes[0], bytes[1], bytes[2], bytes[3] ])
+    }
+
+    #[inline]
+    fn write_le(self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+
+    #[inline]
+    fn to_ip(self) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::from(self))
+    }
+
+    #[inline]
+    fn from_ip(addr: IpAddr) -> Option<Self> {
+        match addr {
+            IpAddr::V4(addr) => Some(u32::from(addr)),
+            IpAddr::V6(_) => None,
+        }
+    }
+
+    #[inline]
+    fn to_u128(self) -> u128 {
+        self as u128
+    }
+
+    #[inline]
+    fn from_u128(n: u128) -> Self {
+        n as u32
+    }
+}
+
+impl Address for u128 {
+    const SIZE: usize = 16;
+    const BITS: u32 = 128;
+
+    #[inline]
+    fn from_le_slice(bytes: &[u8]) -> Self {
+        let mut buf = [0u8; 16];
+        buf.copy_from_slice(&bytes[..16]);
+        u128::from_le_bytes(buf)
+    }
+
+    #[inline]
+    fn write_le(self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+
+    #[inline]
+    fn to_ip(self) -> IpAddr {
+        IpAddr::V6(Ipv6Addr::from(self))
+    }
+
+    #[inline]
+    fn from_ip(addr: IpAddr) -> Option<Self> {
+        match addr {
+            IpAddr::V4(_) => None,
+            IpAddr::V6(addr) => Some(u128::from(addr)),
+        }
+    }
+
+    #[inline]
+    fn to_u128(self) -> u128 {
+        self
+    }
+
+    #[inline]
+    fn from_u128(n: u128) -> Self {
+        n
+    }
+}
@@ -0,0 +1,1077 @@
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::str::FromStr;
+use std::hash::Hash;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use crate::address::Address;
+use crate::country::Country;
+use crate::location::Location;
+use crate::strings;
+use crate::details::RawDetails;
+use crate::csv::{ CsvReader, CsvError };
+use crate::format::{ self, Metadata, Section, Tier };
+use crate::db::{ V4_RECORD_SIZE, V6_RECORD_SIZE };
+
+
+/// Why a CSV row did not make it into the database.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum DropReason {
+    /// The row could not be split into the expected columns.
+    Malformed,
+    /// `ip_from` or `ip_to` is not a number of the right width.
+    InvalidAddress,
+    /// `ip_from` is greater than `ip_to`.
+    InvalidRange,
+    /// The range overlaps (or repeats) a row with a lower `ip_from`.
+    OverlappingRange,
+    /// The country code is `-` or not an ISO 3166 code.
+    UnknownCountry,
+}
+
+impl DropReason {
+    /// Rows dropped for this reason indicate broken input rather than
+    /// unassigned address space.
+    pub fn is_malformed(&self) -> bool {
+        match self {
+            DropReason::Malformed
+            | DropReason::InvalidAddress
+            | DropReason::InvalidRange
+            | DropReason::OverlappingRange => true,
+            DropReason::UnknownCountry => false,
+        }
+    }
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DropReason::Malformed => write!(f, "malformed row"),
+            DropReason::InvalidAddress => write!(f, "invalid address"),
+            DropReason::InvalidRange => write!(f, "ip_from greater than ip_to"),
+            DropReason::OverlappingRange => write!(f, "overlapping range"),
+            DropReason::UnknownCountry => write!(f, "unknown country"),
+        }
+    }
+}
+
+/// At most this many malformed rows are described in `Report::errors`.
+pub const MAX_REPORTED_ERRORS: usize = 20;
+
+#[derive(Debug, Default, Clone)]
+pub struct Report {
+    pub rows_read: usize,
+    pub dropped: HashMap<DropReason, usize>,
+    pub provinces: usize,
+    pub cities: usize,
+    pub v4_records: usize,
+    pub v6_records: usize,
+    /// Records folded into an adjacent record with the same location.
+    pub merged: usize,
+    pub output_size: usize,
+    /// The first `MAX_REPORTED_ERRORS` malformed rows, with line numbers.
+    pub errors: Vec<String>,
+}
+
+impl Report {
+    pub fn dropped(&self, reason: DropReason) -> usize {
+        self.dropped.get(&reason).cloned().unwrap_or(0)
+    }
+
+    pub fn malformed(&self) -> usize {
+        self.dropped.iter()
+            .filter(|(reason, _)| reason.is_malformed())
+            .map(|(_, n)| n)
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry<T> {
+    start: T,
+    end: T,
+    country: Country,
+    province: Option<u32>,
+    city: Option<u32>,
+    details: EntryDetails,
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+struct EntryDetails {
+    latitude: f32,
+    longitude: f32,
+    zip_code: Option<u32>,
+    time_zone: Option<u32>,
+}
+
+// Provinces are keyed by (country, name), cities by (country, province, name)
+// so that same-named places in different parents stay apart.
+type ProvinceKey = (u8, String);
+type CityKey = (u8, Option<u32>, String);
+
+#[derive(Debug)]
+struct Interner<K> {
+    ids: HashMap<K, u32>,
+    keys: Vec<K>,
+}
+
+impl<K> Default for Interner<K> {
+    fn default() -> Self {
+        Self { ids: HashMap::new(), keys: Vec::new() }
+    }
+}
+
+impl<K: Hash + Eq + Clone> Interner<K> {
+    fn intern(&mut self, key: K) -> u32 {
+        if let Some(id) = self.ids.get(&key) {
+            return *id;
+        }
+        let id = self.keys.len() as u32;
+        self.ids.insert(key.clone(), id);
+        self.keys.push(key);
+        id
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns the sorted keys and a map from interned id to sorted index.
+    fn sorted_by<F: Fn(&K, &K) -> Ordering>(&self, compare: F) -> (Vec<&K>, Vec<u32>) {
+        let mut order = (0..self.keys.len()).collect::<Vec<usize>>();
+        order.sort_by(|&a, &b| compare(&self.keys[a], &self.keys[b]));
+
+        let mut remap = vec![0u32; self.keys.len()];
+        for (sorted_idx, &id) in order.iter().enumerate() {
+            remap[id] = sorted_idx as u32;
+        }
+        let keys = order.iter().map(|&id| &self.keys[id]).collect();
+
+        (keys, remap)
+    }
+
+    fn sorted(&self) -> (Vec<&K>, Vec<u32>) where K: Ord {
+        self.sorted_by(K::cmp)
+    }
+}
+
+/// Compiles IP2Location LITE CSV dumps into the binary database format
+/// read by `IpDb`.
+#[derive(Debug)]
+pub struct Builder {
+    dataset: Option<String>,
+    tier: Option<Tier>,
+    country_only: bool,
+    compact: bool,
+    eytzinger: bool,
+    prefix_index: bool,
+    source: Option<String>,
+    v4_entries: Vec<Entry<u32>>,
+    v6_entries: Vec<Entry<u128>>,
+    provinces: Interner<ProvinceKey>,
+    cities: Interner<CityKey>,
+    zip_codes: Interner<String>,
+    time_zones: Interner<String>,
+    report: Report,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self {
+            dataset: None,
+            tier: None,
+            country_only: false,
+            compact: false,
+            eytzinger: false,
+            prefix_index: false,
+            source: None,
+            v4_entries: Vec::new(),
+            v6_entries: Vec::new(),
+            provinces: Interner::default(),
+            cities: Interner::default(),
+            zip_codes: Interner::default(),
+            time_zones: Interner::default(),
+            report: Report::default(),
+        }
+    }
+
+    /// Name stored in the header, defaults to `IP2LOCATION-LITE-DB<tier>`.
+    pub fn set_dataset(&mut self, name: &str) {
+        self.dataset = Some(name.to_string());
+    }
+
+    /// Drops provinces, cities and details so that adjacent ranges of the
+    /// same country merge, for clients that only need `Location::country()`.
+    pub fn set_country_only(&mut self, country_only: bool) {
+        self.country_only = country_only;
+    }
+
+    /// Stores only the range starts and a country index per range, see
+    /// `format::FLAG_COMPACT`. Implies `set_country_only`.
+    pub fn set_compact(&mut self, compact: bool) {
+        self.compact = compact;
+    }
+
+    /// Also writes the range starts in Eytzinger order, see
+    /// `format::SECTION_V4_EYTZINGER`, at the cost of `address + 4` bytes per
+    /// record.
+    pub fn set_eytzinger(&mut self, eytzinger: bool) {
+        self.eytzinger = eytzinger;
+    }
+
+    /// Also writes a /16 jump table for the v4 zone and a sparse /32 one for
+    /// the v6 zone, see `format::SECTION_V4_PREFIX_INDEX`. Lookups then only
+    /// search the few records sharing the address' prefix.
+    pub fn set_prefix_index(&mut self, prefix_index: bool) {
+        self.prefix_index = prefix_index;
+    }
+
+    /// Name prefixed to the `Report::errors` of the rows read next, usually
+    /// the path of the file passed to `add_v4` or `add_v6`.
+    pub fn set_source(&mut self, source: &str) {
+        self.source = Some(source.to_string());
+    }
+
+    /// Tier detected from the column count of the first row read. Every
+    /// later input must be of the same tier, `add_v4` and `add_v6` fail with
+    /// `io::ErrorKind::InvalidData` otherwise, and rows with another number
+    /// of columns are dropped as malformed.
+    pub fn tier(&self) -> Option<Tier> {
+        self.tier
+    }
+
+    pub fn report(&self) -> &Report {
+        &self.report
+    }
+
+    /// Reads an IPv4 CSV dump (`IP2LOCATION-LITE-DB<tier>.CSV`).
+    pub fn add_v4<R: Read>(&mut self, reader: R) -> io::Result<()> {
+        let mut entries = std::mem::take(&mut self.v4_entries);
+        let res = self.read(reader, &mut entries);
+        self.drop_overlapping(&mut entries);
+        self.v4_entries = entries;
+        self.update_report();
+        res
+    }
+
+    /// Reads an IPv6 CSV dump (`IP2LOCATION-LITE-DB<tier>.IPV6.CSV`).
+    pub fn add_v6<R: Read>(&mut self, reader: R) -> io::Result<()> {
+        let mut entries = std::mem::take(&mut self.v6_entries);
+        let res = self.read(reader, &mut entries);
+        self.drop_overlapping(&mut entries);
+        self.v6_entries = entries;
+        self.update_report();
+        res
+    }
+
+    fn update_report(&mut self) {
+        self.report.provinces = self.provinces.len();
+        self.report.cities = self.cities.len();
+        self.report.v4_records = self.v4_entries.len();
+        self.report.v6_records = self.v6_entries.len();
+    }
+
+    fn read<R: Read, T: FromStr + Ord>(&mut self, reader: R, entries: &mut Vec<Entry<T>>) -> io::Result<()> {
+        let mut reader = CsvReader::new(BufReader::new(reader));
+        let mut fields = Vec::new();
+        // NOTE: 每个文件单独识别 tier，和之前的文件不一致时直接报错，
+        //       否则整个文件的行都会被当成格式错误丢掉。
+        let mut tier = None;
+        loop {
+            match reader.read_record(&mut fields) {
+                Ok(true) => {
+                    self.report.rows_read += 1;
+                    if tier.is_none() {
+                        tier = Tier::from_columns(fields.len());
+                        if let Some(tier) = tier {
+                            self.check_tier(tier, reader.record_line())?;
+                        }
+                    }
+                    let res = tier.ok_or(DropReason::Malformed).and_then(|tier| self.parse::<T>(tier, &fields));
+                    match res {
+                        Ok(entry) => entries.push(entry),
+                        Err(reason) => {
+                            let message = self.with_source(format!("line {}: {}", reader.record_line(), reason));
+                            self.drop_row(reason, message);
+                        },
+                    }
+                },
+                Ok(false) => break,
+                Err(CsvError::Io(e)) => return Err(e),
+                Err(e) => {
+                    self.report.rows_read += 1;
+                    let message = self.with_source(e.to_string());
+                    self.drop_row(DropReason::Malformed, message);
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_tier(&mut self, tier: Tier, line: usize) -> io::Result<()> {
+        match self.tier {
+            Some(prev) if prev != tier => {
+                let message = format!("line {}: {:?} rows, the earlier input is {:?}", line, tier, prev);
+                Err(io::Error::new(io::ErrorKind::InvalidData, self.with_source(message)))
+            },
+            _ => {
+                self.tier = Some(tier);
+                Ok(())
+            },
+        }
+    }
+
+    fn with_source(&self, message: String) -> String {
+        match self.source {
+            Some(ref source) => format!("{}: {}", source, message),
+            None => message,
+        }
+    }
+
+    /// Sorts `entries` by start and drops every entry overlapping the one
+    /// before it, the rows may come from several files in any order.
+    fn drop_overlapping<T: Address>(&mut self, entries: &mut Vec<Entry<T>>) {
+        // NOTE: 稳定排序，起始地址相同时保留先读到的那一行。
+        entries.sort_by_key(|entry| entry.start);
+        let mut kept: Vec<Entry<T>> = Vec::with_capacity(entries.len());
+        for entry in entries.drain(..) {
+            if let Some(prev) = kept.last() {
+                if entry.start <= prev.end {
+                    let message = format!("rows {}-{} and {}-{}: {}",
+                        prev.start.to_ip(), prev.end.to_ip(), entry.start.to_ip(), entry.end.to_ip(),
+                        DropReason::OverlappingRange);
+                    self.drop_row(DropReason::OverlappingRange, message);
+                    continue;
+                }
+            }
+            kept.push(entry);
+        }
+        *entries = kept;
+    }
+
+    fn drop_row(&mut self, reason: DropReason, message: String) {
+        *self.report.dropped.entry(reason).or_insert(0) += 1;
+        if reason.is_malformed() && self.report.errors.len() < MAX_REPORTED_ERRORS {
+            self.report.errors.push(message);
+        }
+    }
+
+    fn parse<T: FromStr + Ord>(&mut self, tier: Tier, fields: &[String]) -> Result<Entry<T>, DropReason> {
+        // ip_from, ip_to, country_code, country_name,
+        // region_name, city_name,  (DB3)
+        // latitude, longitude,     (DB5)
+        // zip_code,                (DB9)
+        // time_zone                (DB11)
+        if fields.len() != tier.columns() {
+            return Err(DropReason::Malformed);
+        }
+
+        let start = fields[0].parse::<T>().map_err(|_| DropReason::InvalidAddress)?;
+        let end = fields[1].parse::<T>().map_err(|_| DropReason::InvalidAddress)?;
+        if start > end {
+            return Err(DropReason::InvalidRange);
+        }
+        // NOTE: 如果国家信息是未知的话，那么这条记录没有任何意义。
+        let country = fields[2].parse::<Country>().map_err(|_| DropReason::UnknownCountry)?;
+
+        let mut details = EntryDetails::default();
+        if tier.has_coordinates() {
+            details.latitude = fields[6].parse::<f32>().map_err(|_| DropReason::Malformed)?;
+            details.longitude = fields[7].parse::<f32>().map_err(|_| DropReason::Malformed)?;
+        }
+
+        let mut province = None;
+        let mut city = None;
+        if tier.has_city() {
+            province = known(&fields[4])
+                .map(|name| self.provinces.intern((country.index(), name.to_string())));
+            city = known(&fields[5])
+                .map(|name| self.cities.intern((country.index(), province, name.to_string())));
+        }
+        if tier.has_zip_code() {
+            details.zip_code = known(&fields[8]).map(|name| self.zip_codes.intern(name.to_string()));
+        }
+        if tier.has_time_zone() {
+            details.time_zone = known(&fields[9]).map(|name| self.time_zones.intern(name.to_string()));
+        }
+
+        Ok(Entry { start, end, country, province, city, details })
+    }
+
+    /// Writes the database and returns the number of bytes written.
+    pub fn write<W: Write>(&mut self, w: &mut W) -> io::Result<usize> {
+        let tier = self.tier.unwrap_or(Tier::DB1);
+        let (provinces, province_remap) = self.provinces.sorted();
+        // NOTE: 按省份排序后的下标分组，与 SECTION_CITY_PARENTS 的顺序一致。
+        let city_parent = |key: &CityKey| {
+            let province = key.1.map(|id| province_remap[id as usize]).unwrap_or(u16::MAX as u32);
+            (key.0, province)
+        };
+        let (cities, city_remap) = self.cities.sorted_by(|a, b| {
+            city_parent(a).cmp(&city_parent(b)).then_with(|| a.2.cmp(&b.2))
+        });
+        let (zip_codes, zip_code_remap) = self.zip_codes.sorted();
+        let (time_zones, time_zone_remap) = self.time_zones.sorted();
+        if provinces.len() >= u16::MAX as usize
+            || cities.len() >= u32::MAX as usize
+            || zip_codes.len() >= u32::MAX as usize
+            || time_zones.len() >= u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too many distinct names"));
+        }
+
+        let location = |country: Country, province: Option<u32>, city: Option<u32>| {
+            let province_id = province.map(|id| province_remap[id as usize] as u16).unwrap_or(u16::MAX);
+            let city_id = city.map(|id| city_remap[id as usize]).unwrap_or(u32::MAX);
+            Location::new(country.index(), province_id, city_id)
+        };
+        let raw_details = |details: &EntryDetails| RawDetails {
+            latitude: details.latitude,
+            longitude: details.longitude,
+            zip_code: details.zip_code.map(|id| zip_code_remap[id as usize]).unwrap_or(u32::MAX),
+            time_zone: details.time_zone.map(|id| time_zone_remap[id as usize] as u16).unwrap_or(u16::MAX),
+        };
+
+        // NOTE: add_v4/add_v6 已经排好序并去掉了重叠的行。
+        let country_only = self.country_only || self.compact;
+        let v4_entries = merge_entries(&self.v4_entries, country_only);
+        let v6_entries = merge_entries(&self.v6_entries, country_only);
+
+        let mut v4_zone = Vec::with_capacity(v4_entries.len() * V4_RECORD_SIZE);
+        let mut v4_details_zone = Vec::new();
+        for entry in v4_entries.iter() {
+            write_record(&mut v4_zone, entry, location(entry.country, entry.province, entry.city));
+            raw_details(&entry.details).write(&mut v4_details_zone);
+        }
+
+        let mut v6_zone = Vec::with_capacity(v6_entries.len() * V6_RECORD_SIZE);
+        let mut v6_details_zone = Vec::new();
+        for entry in v6_entries.iter() {
+            write_record(&mut v6_zone, entry, location(entry.country, entry.province, entry.city));
+            raw_details(&entry.details).write(&mut v6_details_zone);
+        }
+
+        let mut v4_starts = v4_entries.iter().map(|entry| entry.start).collect::<Vec<_>>();
+        let mut v6_starts = v6_entries.iter().map(|entry| entry.start).collect::<Vec<_>>();
+
+        let mut flags = 0;
+        let mut zones;
+        let v4_record_count;
+        let v6_record_count;
+        let v4_record_size;
+        let v6_record_size;
+        if self.compact {
+            let (v4_starts_zone, v4_countries) = compact_zones(&v4_entries);
+            let (v6_starts_zone, v6_countries) = compact_zones(&v6_entries);
+            v4_starts = v4_starts_zone.chunks_exact(u32::SIZE).map(u32::from_le_slice).collect();
+            v6_starts = v6_starts_zone.chunks_exact(u128::SIZE).map(u128::from_le_slice).collect();
+            flags |= format::FLAG_COUNTRY_ONLY | format::FLAG_COMPACT;
+            v4_record_count = v4_countries.len();
+            v6_record_count = v6_countries.len();
+            v4_record_size = u32::SIZE + 1;
+            v6_record_size = u128::SIZE + 1;
+            zones = vec![
+                (format::SECTION_V4_STARTS, v4_starts_zone),
+                (format::SECTION_V4_COUNTRIES, v4_countries),
+                (format::SECTION_V6_STARTS, v6_starts_zone),
+                (format::SECTION_V6_COUNTRIES, v6_countries),
+            ];
+        } else {
+            if country_only {
+                flags |= format::FLAG_COUNTRY_ONLY;
+            }
+            v4_record_count = v4_entries.len();
+            v6_record_count = v6_entries.len();
+            v4_record_size = V4_RECORD_SIZE;
+            v6_record_size = V6_RECORD_SIZE;
+            zones = vec![
+                (format::SECTION_V4_RECORDS, v4_zone),
+                (format::SECTION_V6_RECORDS, v6_zone),
+            ];
+        }
+        if self.eytzinger {
+            let (v4_keys, v4_index) = eytzinger_zones(&v4_starts);
+            let (v6_keys, v6_index) = eytzinger_zones(&v6_starts);
+            zones.push((format::SECTION_V4_EYTZINGER, v4_keys));
+            zones.push((format::SECTION_V4_EYTZINGER_INDEX, v4_index));
+            zones.push((format::SECTION_V6_EYTZINGER, v6_keys));
+            zones.push((format::SECTION_V6_EYTZINGER_INDEX, v6_index));
+        }
+        if self.prefix_index {
+            zones.push((format::SECTION_V4_PREFIX_INDEX, v4_prefix_index(&v4_starts, self.compact, &v4_entries)));
+            zones.push((format::SECTION_V6_PREFIX_INDEX, v6_prefix_index(&v6_starts, self.compact, &v6_entries)));
+        }
+        let sections = if country_only { Tier::DB1 } else { tier };
+        if sections.has_city() {
+            let names = provinces.iter().map(|key| key.1.as_str()).collect::<Vec<_>>();
+            let parents = provinces.iter().map(|key| key.0).collect();
+            zones.push((format::SECTION_PROVINCES, string_table(&names)?));
+            zones.push((format::SECTION_PROVINCE_PARENTS, parents));
+
+            let names = cities.iter().map(|key| key.2.as_str()).collect::<Vec<_>>();
+            let mut parents = Vec::with_capacity(cities.len() * format::CITY_PARENT_SIZE);
+            for key in cities.iter() {
+                let (country, province) = city_parent(key);
+                parents.extend_from_slice(&(province as u16).to_le_bytes());
+                parents.extend_from_slice(&[country, 0]);
+            }
+            zones.push((format::SECTION_CITIES, string_table(&names)?));
+            zones.push((format::SECTION_CITY_PARENTS, parents));
+        }
+        if sections.has_coordinates() {
+            zones.push((format::SECTION_V4_DETAILS, v4_details_zone));
+            zones.push((format::SECTION_V6_DETAILS, v6_details_zone));
+        }
+        if sections.has_zip_code() {
+            zones.push((format::SECTION_ZIP_CODES, string_table(&zip_codes)?));
+        }
+        if sections.has_time_zone() {
+            zones.push((format::SECTION_TIME_ZONES, string_table(&time_zones)?));
+        }
+
+        let build_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let dataset = self.dataset.clone()
+            .unwrap_or_else(|| format!("IP2LOCATION-LITE-DB{}", tier.as_u8()));
+        let mut metadata = Metadata {
+            version: format::FORMAT_VERSION,
+            tier,
+            flags,
+            build_timestamp,
+            dataset,
+            v4_record_count: v4_record_count as u32,
+            v4_record_size: v4_record_size as u16,
+            v6_record_count: v6_record_count as u32,
+            v6_record_size: v6_record_size as u16,
+            sections: Vec::new(),
+        };
+
+        let mut offset = format::HEADER_LEN + format::SECTION_ENTRY_LEN * zones.len();
+        for (kind, zone) in zones.iter() {
+            if offset + zone.len() > u32::MAX as usize {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "database exceeds 4 GiB"));
+            }
+            metadata.sections.push(Section { kind: *kind, offset: offset as u32, len: zone.len() as u32 });
+            offset += zone.len();
+        }
+
+        metadata.write(w)?;
+        for (_, zone) in zones.iter() {
+            w.write_all(zone)?;
+        }
+
+        self.report.v4_records = v4_entries.len();
+        self.report.v6_records = v6_entries.len();
+        self.report.merged = self.v4_entries.len() + self.v6_entries.len() - v4_entries.len() - v6_entries.len();
+        self.report.output_size = offset;
+
+        Ok(offset)
+    }
+}
+
+/// Folds each entry into the previous one when the ranges touch and the
+/// location and details are the same. `entries` must be sorted.
+fn merge_entries<T: Address>(entries: &[Entry<T>], country_only: bool) -> Vec<Entry<T>> {
+    let mut merged: Vec<Entry<T>> = Vec::with_capacity(entries.len());
+    for entry in entries.iter() {
+        let mut entry = *entry;
+        if country_only {
+            entry.province = None;
+            entry.city = None;
+            entry.details = EntryDetails::default();
+        }
+        if let Some(last) = merged.last_mut() {
+            let adjacent = last.end.to_u128().checked_add(1) == Some(entry.start.to_u128());
+            let same = last.country == entry.country
+                && last.province == entry.province
+                && last.city == entry.city
+                && last.details == entry.details;
+            if adjacent && same {
+                last.end = entry.end;
+                continue;
+            }
+        }
+        merged.push(entry);
+    }
+
+    merged
+}
+
+/// Range starts and country indexes covering the whole address space, the
+/// space between `entries` is filled with `COMPACT_GAP` ranges.
+fn compact_zones<T: Address>(entries: &[Entry<T>]) -> (Vec<u8>, Vec<u8>) {
+    let mut starts = Vec::with_capacity((entries.len() + 1) * T::SIZE);
+    let mut countries = Vec::with_capacity(entries.len() + 1);
+    let max = T::from_u128(u128::MAX).to_u128();
+    let mut next = Some(0u128);
+    for entry in entries.iter() {
+        if let Some(next) = next {
+            if entry.start.to_u128() > next {
+                T::from_u128(next).write_le(&mut starts);
+                countries.push(format::COMPACT_GAP);
+            }
+        }
+        entry.start.write_le(&mut starts);
+        countries.push(entry.country.index());
+        let end = entry.end.to_u128();
+        next = if end >= max { None } else { Some(end + 1) };
+    }
+    if let Some(next) = next {
+        T::from_u128(next).write_le(&mut starts);
+        countries.push(format::COMPACT_GAP);
+    }
+
+    (starts, countries)
+}
+
+/// Last address of every record, `starts` are the record (or compact) starts.
+fn record_ends<T: Address>(starts: &[T], compact: bool, entries: &[Entry<T>]) -> Vec<T> {
+    if !compact {
+        return entries.iter().map(|entry| entry.end).collect();
+    }
+    // NOTE: 紧凑格式每段结束于下一段开始之前，最后一段到地址空间末尾。
+    starts.iter()
+        .skip(1)
+        .map(|start| T::from_u128(start.to_u128() - 1))
+        .chain(if starts.is_empty() { None } else { Some(T::from_u128(u128::MAX)) })
+        .collect()
+}
+
+fn v4_prefix_index(starts: &[u32], compact: bool, entries: &[Entry<u32>]) -> Vec<u8> {
+    let ends = record_ends(starts, compact, entries);
+    let mut index = Vec::with_capacity(format::V4_PREFIX_INDEX_LEN * 4);
+    let mut first = 0;
+    for prefix in 0..format::V4_PREFIX_INDEX_LEN as u64 {
+        let bucket_start = prefix << 16;
+        while first < ends.len() && (ends[first] as u64) < bucket_start {
+            first += 1;
+        }
+        index.extend_from_slice(&(first as u32).to_le_bytes());
+    }
+
+    index
+}
+
+fn v6_prefix_index(starts: &[u128], compact: bool, entries: &[Entry<u128>]) -> Vec<u8> {
+    let ends = record_ends(starts, compact, entries);
+    let mut prefixes = starts.iter().map(|start| (start >> 96) as u32).collect::<Vec<_>>();
+    prefixes.push(0);
+    prefixes.sort_unstable();
+    prefixes.dedup();
+
+    let mut index = Vec::with_capacity(prefixes.len() * format::V6_PREFIX_ENTRY_SIZE);
+    let mut first = 0;
+    for &prefix in prefixes.iter() {
+        let bucket_start = (prefix as u128) << 96;
+        while first < ends.len() && ends[first] < bucket_start {
+            first += 1;
+        }
+        index.extend_from_slice(&prefix.to_le_bytes());
+        index.extend_from_slice(&(first as u32).to_le_bytes());
+    }
+
+    index
+}
+
+/// Keys and record indexes in Eytzinger order for the sorted `starts`.
+fn eytzinger_zones<T: Address>(starts: &[T]) -> (Vec<u8>, Vec<u8>) {
+    let mut order = vec![0u32; starts.len()];
+    let mut next = 0;
+    eytzinger_fill(&mut order, &mut next, 1);
+
+    let mut keys = Vec::with_capacity(starts.len() * T::SIZE);
+    let mut index = Vec::with_capacity(starts.len() * format::EYTZINGER_INDEX_SIZE);
+    for &idx in order.iter() {
+        starts[idx as usize].write_le(&mut keys);
+        index.extend_from_slice(&idx.to_le_bytes());
+    }
+
+    (keys, index)
+}
+
+/// In-order walk of the implicit tree rooted at the 1-based position `k`.
+fn eytzinger_fill(order: &mut [u32], next: &mut u32, k: usize) {
+    if k <= order.len() {
+        eytzinger_fill(order, next, 2 * k);
+        order[k - 1] = *next;
+        *next += 1;
+        eytzinger_fill(order, next, 2 * k + 1);
+    }
+}
+
+/// IP2Location writes `-` for unknown names.
+fn known(name: &str) -> Option<&str> {
+    if name == "-" {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+fn string_table<S: AsRef<str>>(strings: &[S]) -> io::Result<Vec<u8>> {
+    let mut zone = Vec::with_capacity(strings::table_len(strings));
+    strings::write_table(&mut zone, strings)?;
+    Ok(zone)
+}
+
+fn write_record<T: Address>(zone: &mut Vec<u8>, entry: &Entry<T>, location: Location) {
+    entry.start.write_le(zone);
+    entry.end.write_le(zone);
+    zone.extend_from_slice(&location.0.to_le_bytes());
+}
+
+
+#[cfg(test)]
+pub(crate) const SAMPLE_V4_CSV: &str = "\
+\"0\",\"16777215\",\"-\",\"-\",\"-\",\"-\"
+\"16777216\",\"16777471\",\"US\",\"United States of America\",\"California\",\"Los Angeles\"
+\"16777472\",\"16778239\",\"CN\",\"China\",\"Fujian\",\"Fuzhou\"
+\"16778240\",\"16779263\",\"AU\",\"Australia\",\"Victoria\",\"Melbourne\"
+";
+
+#[cfg(test)]
+pub(crate) const SAMPLE_V6_CSV: &str = "\
+\"42540528726795050063891204319802818560\",\"42540528806023212578155541913346768895\",\"JP\",\"Japan\",\"Tokyo\",\"Tokyo\"
+";
+
+/// Builds a database from the given CSV dumps, `configure` picks the layout.
+#[cfg(test)]
+pub(crate) fn build_sample<F: FnOnce(&mut Builder)>(v4_csv: &str, v6_csv: &str, configure: F) -> Vec<u8> {
+    let mut builder = Builder::new();
+    builder.add_v4(v4_csv.as_bytes()).unwrap();
+    builder.add_v6(v6_csv.as_bytes()).unwrap();
+    configure(&mut builder);
+    let mut buf = Vec::new();
+    builder.write(&mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn test_build() {
+    let mut builder = Builder::new();
+    builder.add_v4(SAMPLE_V4_CSV.as_bytes()).unwrap();
+    builder.add_v6(SAMPLE_V6_CSV.as_bytes()).unwrap();
+    builder.add_v4("not a csv row".as_bytes()).unwrap();
+
+    let mut buf = Vec::new();
+    let size = builder.write(&mut buf).unwrap();
+    assert_eq!(size, buf.len());
+
+    let report = builder.report();
+    assert_eq!(report.rows_read, 6);
+    assert_eq!(report.dropped(DropReason::UnknownCountry), 1);
+    assert_eq!(report.malformed(), 1);
+    assert_eq!(report.provinces, 4);
+    assert_eq!(report.cities, 4);
+    assert_eq!(report.errors, vec!["line 1: malformed row".to_string()]);
+
+    builder.set_source("extra.csv");
+    builder.add_v4("\"1\",\"2\",\"US\"\n\"3".as_bytes()).unwrap();
+    assert_eq!(builder.report().errors[1..], [
+        "extra.csv: line 1: malformed row".to_string(),
+        "extra.csv: line 2: unterminated quoted field".to_string(),
+    ]);
+
+    let db = crate::db::IpDb::from_bytes(buf).unwrap();
+    db.validate().unwrap();
+    let location = db.query(&"1.0.1.1".parse().unwrap()).unwrap();
+    assert_eq!(location.country(), Country::CN);
+    assert_eq!(location.province().unwrap().name(&db), Some("Fujian"));
+    assert_eq!(location.city().unwrap().name(&db), Some("Fuzhou"));
+    let location = db.query(&"2001:200::1".parse().unwrap()).unwrap();
+    assert_eq!(location.country(), Country::JP);
+    assert!(db.query(&"0.0.0.1".parse().unwrap()).is_none());
+}
+
+#[test]
+fn test_build_db11() {
+    let csv = "\
+\"16777216\",\"16777471\",\"US\",\"United States of America\",\"California\",\"Los Angeles\",\"34.052230\",\"-118.243680\",\"90001\",\"-07:00\"
+\"16777472\",\"16778239\",\"CN\",\"China\",\"Fujian\",\"Fuzhou\",\"26.061390\",\"119.306110\",\"-\",\"+08:00\"
+\"16778240\",\"16779263\",\"AU\",\"Australia\",\"Victoria\",\"Melbourne\",\"-37.814000\"
+";
+    let mut builder = Builder::new();
+    builder.add_v4(csv.as_bytes()).unwrap();
+    assert_eq!(builder.tier(), Some(Tier::DB11));
+    assert_eq!(builder.report().malformed(), 1);
+
+    let mut buf = Vec::new();
+    builder.write(&mut buf).unwrap();
+    let db = crate::db::IpDb::from_bytes(buf).unwrap();
+    db.validate().unwrap();
+    assert_eq!(db.metadata().tier, Tier::DB11);
+    assert_eq!(db.metadata().dataset, "IP2LOCATION-LITE-DB11");
+
+    let details = db.details(&"1.0.0.1".parse().unwrap()).unwrap();
+    assert_eq!(details.latitude, Some(34.05223));
+    assert_eq!(details.longitude, Some(-118.24368));
+    assert_eq!(details.zip_code, Some("90001"));
+    assert_eq!(details.time_zone, Some("-07:00"));
+
+    let details = db.details(&"1.0.1.1".parse().unwrap()).unwrap();
+    assert_eq!(details.zip_code, None);
+    assert_eq!(details.time_zone, Some("+08:00"));
+}
+
+#[test]
+fn test_build_tier_mismatch() {
+    let db5 = "\
+\"42540528726795050063891204319802818560\",\"42540528806023212578155541913346768895\",\"JP\",\"Japan\",\"Tokyo\",\"Tokyo\",\"35.689500\",\"139.691710\"
+";
+    let mut builder = Builder::new();
+    builder.add_v4(SAMPLE_V4_CSV.as_bytes()).unwrap();
+    builder.set_source("db5.csv");
+    let e = builder.add_v6(db5.as_bytes()).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(e.to_string(), "db5.csv: line 1: DB5 rows, the earlier input is DB3");
+    assert_eq!(builder.tier(), Some(Tier::DB3));
+    assert_eq!(builder.report().v6_records, 0);
+    assert_eq!(builder.report().malformed(), 0);
+}
+
+#[test]
+fn test_build_bad_ranges() {
+    let reversed = "\
+\"16777471\",\"16777216\",\"US\",\"United States of America\"
+\"16777472\",\"16778239\",\"CN\",\"China\"
+";
+    // NOTE: 第二个文件与第一个重叠，且顺序打乱，重叠检查要跨文件。
+    let overlapping = "\
+\"16777472\",\"16777727\",\"JP\",\"Japan\",\"Tokyo\",\"Tokyo\"
+\"16777216\",\"16777500\",\"US\",\"United States of America\",\"California\",\"Los Angeles\"
+";
+    let duplicate = "\
+\"16777216\",\"16777471\",\"US\",\"United States of America\"
+\"16777216\",\"16777471\",\"US\",\"United States of America\"
+";
+    let cases = [
+        (vec![reversed], DropReason::InvalidRange, "1.0.1.1", "CN"),
+        (vec![overlapping, SAMPLE_V4_CSV], DropReason::OverlappingRange, "1.0.1.1", "US"),
+        (vec![duplicate], DropReason::OverlappingRange, "1.0.0.1", "US"),
+    ];
+    for (csvs, reason, addr, country) in cases.iter() {
+        let mut builder = Builder::new();
+        for csv in csvs.iter() {
+            builder.add_v4(csv.as_bytes()).unwrap();
+        }
+        assert!(builder.report().dropped(*reason) >= 1, "{:?}", reason);
+        assert!(builder.report().malformed() >= 1);
+        assert!(!builder.report().errors.is_empty());
+
+        let mut buf = Vec::new();
+        builder.write(&mut buf).unwrap();
+        let db = crate::db::IpDb::from_bytes(buf).unwrap();
+        db.validate().unwrap();
+        let location = db.query(&addr.parse().unwrap()).unwrap();
+        assert_eq!(location.country().code(), *country);
+    }
+}
+
+#[test]
+fn test_build_merge() {
+    let csv = "\
+\"16777216\",\"16777471\",\"CN\",\"China\",\"Fujian\",\"Fuzhou\"
+\"16777472\",\"16777727\",\"CN\",\"China\",\"Fujian\",\"Fuzhou\"
+\"16777728\",\"16777983\",\"CN\",\"China\",\"Fujian\",\"Xiamen\"
+\"16778240\",\"16778495\",\"CN\",\"China\",\"Fujian\",\"Xiamen\"
+\"16778496\",\"16778751\",\"CN\",\"China\",\"Beijing\",\"Beijing\"
+";
+    let mut builder = Builder::new();
+    builder.add_v4(csv.as_bytes()).unwrap();
+    let mut buf = Vec::new();
+    builder.write(&mut buf).unwrap();
+    assert_eq!(builder.report().v4_records, 4);
+    assert_eq!(builder.report().merged, 1);
+    let db = crate::db::IpDb::from_bytes(buf).unwrap();
+    db.validate().unwrap();
+    let record = db.v4_records().get(0).unwrap();
+    assert_eq!((record.start, record.end), (16777216, 16777727));
+    assert!(!db.metadata().is_country_only());
+
+    builder.set_country_only(true);
+    let mut compact = Vec::new();
+    builder.write(&mut compact).unwrap();
+    // NOTE: 1.0.3.0/24 不在数据里，两边不会合并。
+    assert_eq!(builder.report().v4_records, 2);
+    assert_eq!(builder.report().merged, 3);
+    let db = crate::db::IpDb::from_bytes(compact).unwrap();
+    db.validate().unwrap();
+    assert!(db.metadata().is_country_only());
+    assert_eq!(db.metadata().tier, Tier::DB3);
+    assert!(db.provinces().is_empty());
+    let location = db.query(&"1.0.5.1".parse().unwrap()).unwrap();
+    assert_eq!(location.country(), Country::CN);
+    assert_eq!(location.province(), None);
+    assert!(db.query(&"1.0.3.1".parse().unwrap()).is_none());
+}
+
+#[test]
+fn test_build_same_names() {
+    let csv = "\
+\"16777216\",\"16777471\",\"US\",\"United States of America\",\"Illinois\",\"Springfield\"
+\"16777472\",\"16777727\",\"US\",\"United States of America\",\"Missouri\",\"Springfield\"
+\"16777728\",\"16777983\",\"US\",\"United States of America\",\"Georgia\",\"Atlanta\"
+\"16777984\",\"16778239\",\"IN\",\"India\",\"Punjab\",\"Amritsar\"
+\"16778240\",\"16778495\",\"PK\",\"Pakistan\",\"Punjab\",\"Lahore\"
+";
+    let mut builder = Builder::new();
+    builder.add_v4(csv.as_bytes()).unwrap();
+    assert_eq!(builder.report().provinces, 5);
+    assert_eq!(builder.report().cities, 5);
+
+    let mut buf = Vec::new();
+    builder.write(&mut buf).unwrap();
+    let db = crate::db::IpDb::from_bytes(buf).unwrap();
+    db.validate().unwrap();
+
+    use crate::location::{ City, Province };
+    let springfields = City::find(&db, Country::US, "Springfield");
+    assert_eq!(springfields.len(), 2);
+    let states = springfields.iter()
+        .map(|city| city.province(&db).unwrap().name(&db).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(states, vec!["Illinois", "Missouri"]);
+    assert!(springfields.iter().all(|city| city.country(&db) == Some(Country::US)));
+    assert!(City::find(&db, Country::CN, "Springfield").is_empty());
+
+    let location = db.query(&"1.0.1.1".parse().unwrap()).unwrap();
+    let missouri = Province::find(&db, Country::US, "Missouri").unwrap();
+    assert_eq!(location.province(), Some(missouri));
+    assert_eq!(City::find_in(&db, missouri, "Springfield"), location.city());
+
+    let india = "IN".parse::<Country>().unwrap();
+    let pakistan = "PK".parse::<Country>().unwrap();
+    let punjab = Province::find(&db, india, "Punjab").unwrap();
+    assert_ne!(Some(punjab), Province::find(&db, pakistan, "Punjab"));
+    assert_eq!(punjab.country(&db), Some(india));
+    assert!(Province::find(&db, Country::GB, "Georgia").is_none());
+    assert!(City::find_in(&db, punjab, "Lahore").is_none());
+}
+
+#[test]
+fn test_build_compact() {
+    let buf = build_sample(SAMPLE_V4_CSV, SAMPLE_V6_CSV, |builder| builder.set_compact(true));
+    let db = crate::db::IpDb::from_bytes(buf).unwrap();
+    db.validate().unwrap();
+    assert!(db.metadata().is_compact());
+    assert!(db.metadata().is_country_only());
+    // NOTE: 0.0.0.0 开头的空段，三段记录和末尾的空段。
+    assert_eq!(db.v4_records().len(), 5);
+    assert_eq!(db.v4_records().iter().count(), 3);
+
+    assert!(db.query(&"0.0.0.1".parse().unwrap()).is_none());
+    let location = db.query(&"1.0.1.1".parse().unwrap()).unwrap();
+    assert_eq!(location.country(), Country::CN);
+    assert_eq!(location.city(), None);
+    assert!(db.query(&"255.255.255.255".parse().unwrap()).is_none());
+    assert_eq!(db.query(&"2001:200::1".parse().unwrap()).map(|l| l.country()), Some(Country::JP));
+    assert!(db.query(&"2001:300::".parse().unwrap()).is_none());
+
+    let ranges = db.ranges(Country::CN).collect::<Vec<_>>();
+    assert_eq!(ranges, vec![("1.0.1.0".parse().unwrap(), "1.0.3.255".parse().unwrap())]);
+}
+
+#[test]
+fn test_build_eytzinger() {
+    // NOTE: 不同的记录数对应不同形状的树，逐个与普通布局的结果比较。
+    for n in 0..40u32 {
+        let mut csv = String::new();
+        let mut probes = Vec::new();
+        for i in 0..n {
+            let (start, end) = (i * 1000 + 100, i * 1000 + 100 + i * 10);
+            let country = ["US", "CN", "JP"][i as usize % 3];
+            csv.push_str(&format!("\"{}\",\"{}\",\"{}\",\"-\"\n", start, end, country));
+            probes.extend_from_slice(&[start - 1, start, end, end + 1]);
+        }
+
+        for compact in [false, true].iter() {
+            let build = |eytzinger: bool| {
+                let buf = build_sample(&csv, "", |builder| {
+                    builder.set_compact(*compact);
+                    builder.set_eytzinger(eytzinger);
+                });
+                crate::db::IpDb::from_bytes(buf).unwrap()
+            };
+            let plain = build(false);
+            let db = build(true);
+            db.validate().unwrap();
+            assert!(db.metadata().section(format::SECTION_V4_EYTZINGER).is_some());
+            for probe in probes.iter().cloned().chain(vec![0, u32::MAX]) {
+                let addr = std::net::IpAddr::from(std::net::Ipv4Addr::from(probe));
+                assert_eq!(db.lookup(&addr), plain.lookup(&addr), "n = {}, {}", n, addr);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_eytzinger_corrupt_index() {
+    for compact in [false, true].iter() {
+        let mut buf = build_sample(SAMPLE_V4_CSV, "", |builder| {
+            builder.set_compact(*compact);
+            builder.set_eytzinger(true);
+        });
+
+        let section = Metadata::parse(&buf).unwrap().section(format::SECTION_V4_EYTZINGER_INDEX).cloned().unwrap();
+        for offset in (section.offset..section.offset + section.len).step_by(format::EYTZINGER_INDEX_SIZE) {
+            let offset = offset as usize;
+            buf[offset..offset + 4].copy_from_slice(&999u32.to_le_bytes());
+        }
+        let db = crate::db::IpDb::from_bytes(buf).unwrap();
+        for addr in ["0.0.0.1", "1.0.1.1", "255.255.255.255"].iter() {
+            let _ = db.query(&addr.parse().unwrap());
+        }
+        assert!(db.validate().is_err());
+    }
+}
+
+#[test]
+fn test_build_prefix_index() {
+    // NOTE: 段的长度逐渐变大，会跨过 /16 (v6 为 /32) 的边界，也会留下空的前缀。
+    let mut v4_csv = String::new();
+    let mut v6_csv = String::new();
+    let mut probes = vec![0u128, u32::MAX as u128];
+    for i in 0..60u128 {
+        let (start, end) = (i * 40000 + 100, i * 40000 + 100 + i * 3000 % 39000);
+        let country = ["US", "CN", "JP"][i as usize % 3];
+        v4_csv.push_str(&format!("\"{}\",\"{}\",\"{}\",\"-\"\n", start, end, country));
+        v6_csv.push_str(&format!("\"{}\",\"{}\",\"{}\",\"-\"\n", start << 80, (end << 80) | 0xff, country));
+        probes.extend_from_slice(&[start - 1, start, end, end + 1]);
+    }
+
+    let build = |compact: bool, prefix_index: bool| {
+        let buf = build_sample(&v4_csv, &v6_csv, |builder| {
+            builder.set_compact(compact);
+            builder.set_prefix_index(prefix_index);
+        });
+        crate::db::IpDb::from_bytes(buf).unwrap()
+    };
+    for compact in [false, true].iter() {
+        let plain = build(*compact, false);
+        let db = build(*compact, true);
+        db.validate().unwrap();
+        assert!(db.metadata().section(format::SECTION_V4_PREFIX_INDEX).is_some());
+        assert!(db.metadata().section(format::SECTION_V6_PREFIX_INDEX).is_some());
+
+        for probe in probes.iter() {
+            let addrs = [
+                std::net::IpAddr::from(std::net::Ipv4Addr::from(*probe as u32)),
+                std::net::IpAddr::from(std::net::Ipv6Addr::from(probe << 80)),
+                std::net::IpAddr::from(std::net::Ipv6Addr::from((probe << 80) | 0xff)),
+            ];
+            for addr in addrs.iter() {
+                assert_eq!(db.lookup(addr), plain.lookup(addr), "{}", addr);
+            }
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt;
+use std::str::FromStr;
+use std::net::IpAddr;
+
+use crate::address::Address;
+
+
+/// An address block, `network` has no bits set past `prefix_len`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Cidr<T> {
+    pub network: T,
+    pub prefix_len: u8,
+}
+
+impl<T: Address> Cidr<T> {
+    pub fn first(&self) -> T {
+        self.network
+    }
+
+    pub fn last(&self) -> T {
+        let host_bits = T::BITS - self.prefix_len as u32;
+        T::from_u128(self.network.to_u128() | host_mask(host_bits))
+    }
+
+    pub fn contains(&self, addr: T) -> bool {
+        addr >= self.first() && addr <= self.last()
+    }
+}
+
+impl<T: Address> fmt::Display for Cidr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.network.to_ip(), self.prefix_len)
+    }
+}
+
+/// Parses `network/prefix_len`, host bits in `network` are cleared.
+impl<T: Address> FromStr for Cidr<T> {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (network, prefix_len) = s.split_once('/').ok_or(())?;
+        let network = network.parse::<IpAddr>().ok().and_then(T::from_ip).ok_or(())?;
+        let prefix_len = prefix_len.parse::<u8>().map_err(|_| ())?;
+        if prefix_len as u32 > T::BITS {
+            return Err(());
+        }
+        let network = network.to_u128() & !host_mask(T::BITS - prefix_len as u32);
+
+        Ok(Cidr { network: T::from_u128(network), prefix_len })
+    }
+}
+
+/// IPv4 and IPv6 prefixes, each list sorted and non-overlapping.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct CidrSet {
+    pub v4: Vec<Cidr<u32>>,
+    pub v6: Vec<Cidr<u128>>,
+}
+
+impl CidrSet {
+    pub fn from_ranges<V4, V6>(v4: V4, v6: V6) -> Self
+        where V4: IntoIterator<Item = (u32, u32)>,
+              V6: IntoIterator<Item = (u128, u128)> {
+        Self { v4: to_cidrs(v4), v6: to_cidrs(v6) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.v4.len() + self.v6.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.v4.is_empty() && self.v6.is_empty()
+    }
+
+    /// Adds `cidr` of either family, e.g. `"192.168.0.0/16"`. Returns
+    /// `false` if it is not a valid prefix.
+    pub fn insert(&mut self, cidr: &str) -> bool {
+        if let Ok(cidr) = cidr.parse::<Cidr<u32>>() {
+            self.v4 = to_cidrs(self.v4.iter().chain(Some(&cidr)).map(|c| (c.first(), c.last())));
+        } else if let Ok(cidr) = cidr.parse::<Cidr<u128>>() {
+            self.v6 = to_cidrs(self.v6.iter().chain(Some(&cidr)).map(|c| (c.first(), c.last())));
+        } else {
+            return false;
+        }
+
+        true
+    }
+
+    pub fn union(&self, other: &CidrSet) -> CidrSet {
+        CidrSet::from_ranges(
+            self.v4.iter().chain(other.v4.iter()).map(|c| (c.first(), c.last())),
+            self.v6.iter().chain(other.v6.iter()).map(|c| (c.first(), c.last())),
+        )
+    }
+
+    /// Every address not in the set, e.g. the routes to send through a
+    /// tunnel when the set is the traffic to keep local.
+    pub fn complement(&self) -> CidrSet {
+        CidrSet::from_ranges(
+            complement(self.v4.iter().map(|c| (c.first(), c.last()))),
+            complement(self.v6.iter().map(|c| (c.first(), c.last()))),
+        )
+    }
+}
+
+#[inline]
+fn host_mask(host_bits: u32) -> u128 {
+    if host_bits >= 128 {
+        u128::MAX
+    } else {
+        (1u128 << host_bits) - 1
+    }
+}
+
+/// Sorts inclusive `(start, end)` ranges and joins the overlapping and
+/// adjacent ones. Ranges with `start > end` are ignored.
+pub fn merge<T: Address, I: IntoIterator<Item = (T, T)>>(ranges: I) -> Vec<(T, T)> {
+    let mut ranges = ranges.into_iter()
+        .filter(|(start, end)| start <= end)
+        .collect::<Vec<_>>();
+    ranges.sort();
+
+    let mut merged: Vec<(T, T)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        if let Some(last) = merged.last_mut() {
+            if start.to_u128() <= last.1.to_u128().saturating_add(1) {
+                if end > last.1 {
+                    last.1 = end;
+                }
+                continue;
+            }
+        }
+        merged.push((start, end));
+    }
+
+    merged
+}
+
+/// The ranges not covered by `ranges`, in ascending order.
+pub fn complement<T: Address, I: IntoIterator<Item = (T, T)>>(ranges: I) -> Vec<(T, T)> {
+    let max = host_mask(T::BITS);
+    let mut gaps = Vec::new();
+    let mut next = Some(0u128);
+    for (start, end) in merge(ranges) {
+        let (start, end) = (start.to_u128(), end.to_u128());
+        if let Some(next) = next {
+            if start > next {
+                gaps.push((T::from_u128(next), T::from_u128(start - 1)));
+            }
+        }
+        next = if end >= max { None } else { Some(end + 1) };
+    }
+    if let Some(next) = next {
+        gaps.push((T::from_u128(next), T::from_u128(max)));
+    }
+
+    gaps
+}
+
+/// The shortest list of prefixes covering exactly the union of `ranges`,
+/// in ascending order.
+pub fn to_cidrs<T: Address, I: IntoIterator<Item = (T, T)>>(ranges: I) -> Vec<Cidr<T>> {
+    let mut cidrs = Vec::new();
+    for (start, end) in merge(ranges) {
+        split(start, end, &mut cidrs);
+    }
+
+    cidrs
+}
+
+/// Appends the prefixes covering the inclusive range `start..=end`.
+pub fn split<T: Address>(start: T, end: T, cidrs: &mut Vec<Cidr<T>>) {
+    let end = end.to_u128();
+    let mut start = start.to_u128();
+    while start <= end {
+        // NOTE: 块的大小受起始地址的对齐和剩余长度两者限制。
+        let mut host_bits = start.trailing_zeros().min(T::BITS);
+        while start + host_mask(host_bits) > end {
+            host_bits -= 1;
+        }
+        cidrs.push(Cidr { network: T::from_u128(start), prefix_len: (T::BITS - host_bits) as u8 });
+
+        let last = start + host_mask(host_bits);
+        if last >= end {
+            break;
+        }
+        start = last + 1;
+    }
+}
+
+
+#[test]
+fn test_to_cidrs() {
+    let ip = |s: &str| u32::from(s.parse::<std::net::Ipv4Addr>().unwrap());
+    let cidrs = to_cidrs(vec![
+        (ip("1.0.2.0"), ip("1.0.3.255")),
+        (ip("1.0.1.0"), ip("1.0.1.255")),
+        (ip("1.0.4.0"), ip("1.0.4.0")),
+        (ip("1.0.9.0"), ip("1.0.8.0")),
+    ]);
+    let cidrs = cidrs.iter().map(|cidr| cidr.to_string()).collect::<Vec<_>>();
+    assert_eq!(cidrs, vec!["1.0.1.0/24", "1.0.2.0/23", "1.0.4.0/32"]);
+
+    assert_eq!(to_cidrs(vec![(0u32, u32::MAX)]), vec![Cidr { network: 0, prefix_len: 0 }]);
+    assert_eq!(to_cidrs(vec![(0u128, u128::MAX)]), vec![Cidr { network: 0, prefix_len: 0 }]);
+    assert_eq!(to_cidrs(vec![(u128::MAX, u128::MAX)]), vec![Cidr { network: u128::MAX, prefix_len: 128 }]);
+    assert_eq!(to_cidrs(vec![(1u32, 6u32)]).len(), 4);
+
+    let cidr = Cidr { network: ip("10.0.0.0"), prefix_len: 8 };
+    assert_eq!(cidr.last(), ip("10.255.255.255"));
+    assert!(cidr.contains(ip("10.1.2.3")));
+    assert!(!cidr.contains(ip("11.0.0.0")));
+}
+
+#[test]
+fn test_complement() {
+    assert_eq!(complement(vec![(0u32, 9), (20, u32::MAX)]), vec![(10, 19)]);
+    assert_eq!(complement(vec![(5u32, 9)]), vec![(0, 4), (10, u32::MAX)]);
+    assert_eq!(complement(Vec::<(u128, u128)>::new()), vec![(0, u128::MAX)]);
+    assert!(complement(vec![(0u128, u128::MAX)]).is_empty());
+
+    let mut set = CidrSet::default();
+    assert!(set.insert("1.0.0.0/8"));
+    assert!(set.insert("2001:db8::1/32"));
+    assert!(!set.insert("10.0.0.0/33"));
+    assert_eq!(set.v6[0].to_string(), "2001:db8::/32");
+
+    let routes = set.complement();
+    let v4 = routes.v4.iter().map(|cidr| cidr.to_string()).collect::<Vec<_>>();
+    assert_eq!(v4, vec!["0.0.0.0/8", "2.0.0.0/7", "4.0.0.0/6", "8.0.0.0/5", "16.0.0.0/4",
+                        "32.0.0.0/3", "64.0.0.0/2", "128.0.0.0/1"]);
+    assert_eq!(routes.complement(), set);
+    assert_eq!(set.union(&routes).complement(), CidrSet::default());
+}
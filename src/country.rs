@@ -6,7 +6,7 @@ use std::str::FromStr;
 // 
 // Download from: https://github.com/lukes/ISO-3166-Countries-with-Regional-Codes
 pub const COUNTRY_CODES_LEN: usize = 249;
-pub static COUNTRY_CODES: [(&'static str, &'static str); COUNTRY_CODES_LEN] = [
+pub static COUNTRY_CODES: [(&str, &str); COUNTRY_CODES_LEN] = [
     ("AD", "Andorra"), 
     ("AE", "United Arab Emirates"), 
     ("AF", "Afghanistan"), 
@@ -286,6 +286,9 @@ impl Country {
         Country(idx)
     }
     
+    /// # Safety
+    ///
+    /// `idx` must be less than `COUNTRY_CODES_LEN`.
     #[inline]
     pub const unsafe fn from_index_unchecked(idx: u8) -> Self {
         Country(idx)
@@ -307,9 +310,9 @@ impl Country {
     }
 }
 
-impl Into<u8> for Country {
-    fn into(self) -> u8 {
-        self.0
+impl From<Country> for u8 {
+    fn from(country: Country) -> u8 {
+        country.0
     }
 }
 
@@ -0,0 +1,165 @@
+use std::fmt;
+use std::io::{self, BufRead};
+
+
+#[derive(Debug)]
+pub enum CsvError {
+    Io(io::Error),
+    InvalidUtf8 { line: usize },
+    /// A quoted field is still open at the end of the input.
+    UnterminatedQuote { line: usize },
+    /// A quote inside an unquoted field, or text after a closing quote.
+    UnexpectedQuote { line: usize },
+}
+
+impl CsvError {
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            CsvError::Io(_) => None,
+            CsvError::InvalidUtf8 { line }
+            | CsvError::UnterminatedQuote { line }
+            | CsvError::UnexpectedQuote { line } => Some(*line),
+        }
+    }
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CsvError::Io(e) => write!(f, "io error: {}", e),
+            CsvError::InvalidUtf8 { line } => write!(f, "line {}: invalid UTF-8", line),
+            CsvError::UnterminatedQuote { line } => write!(f, "line {}: unterminated quoted field", line),
+            CsvError::UnexpectedQuote { line } => write!(f, "line {}: unexpected quote", line),
+        }
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+impl From<io::Error> for CsvError {
+    fn from(e: io::Error) -> Self {
+        CsvError::Io(e)
+    }
+}
+
+/// Streaming RFC 4180 reader.
+///
+/// Reads one line at a time, so memory use is bounded by the longest record.
+/// Quoted fields may contain commas, line breaks and `""` escaped quotes.
+/// Empty lines are skipped. After a syntax error the reader continues with
+/// the next line.
+pub struct CsvReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    lines_read: usize,
+    record_line: usize,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, buf: Vec::new(), lines_read: 0, record_line: 0 }
+    }
+
+    /// Line number (1-based) where the last record started.
+    pub fn record_line(&self) -> usize {
+        self.record_line
+    }
+
+    /// Reads the next record into `record`, returns `false` at the end of input.
+    pub fn read_record(&mut self, record: &mut Vec<String>) -> Result<bool, CsvError> {
+        record.clear();
+
+        let mut field = String::new();
+        let mut in_quotes = false;
+        let mut quoted = false;
+        let mut started = false;
+        loop {
+            self.buf.clear();
+            if self.reader.read_until(b'\n', &mut self.buf)? == 0 {
+                if in_quotes {
+                    return Err(CsvError::UnterminatedQuote { line: self.record_line });
+                }
+                return Ok(false);
+            }
+            self.lines_read += 1;
+            let line = std::str::from_utf8(&self.buf)
+                .map_err(|_| CsvError::InvalidUtf8 { line: self.lines_read })?;
+
+            if !started {
+                if line.trim_end_matches(['\r', '\n']).is_empty() {
+                    continue;
+                }
+                started = true;
+                self.record_line = self.lines_read;
+            }
+
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                if in_quotes {
+                    if c == '"' {
+                        if chars.peek() == Some(&'"') {
+                            chars.next();
+                            field.push('"');
+                        } else {
+                            in_quotes = false;
+                        }
+                    } else {
+                        field.push(c);
+                    }
+                    continue;
+                }
+
+                match c {
+                    ',' => {
+                        record.push(std::mem::take(&mut field));
+                        quoted = false;
+                    },
+                    '\n' => break,
+                    '\r' if chars.peek() == Some(&'\n') => break,
+                    '"' if field.is_empty() && !quoted => {
+                        in_quotes = true;
+                        quoted = true;
+                    },
+                    _ if quoted => return Err(CsvError::UnexpectedQuote { line: self.lines_read }),
+                    '"' => return Err(CsvError::UnexpectedQuote { line: self.lines_read }),
+                    _ => field.push(c),
+                }
+            }
+
+            if !in_quotes {
+                record.push(field);
+                return Ok(true);
+            }
+        }
+    }
+}
+
+
+#[test]
+fn test_csv_reader() {
+    let data = "\"1\",\"2\",\"a, b\"\r\n\n3,\"say \"\"hi\"\"\",\"multi\nline\"\n\"x\"y\n4,,\"\"";
+    let mut reader = CsvReader::new(data.as_bytes());
+    let mut record = Vec::new();
+
+    assert!(reader.read_record(&mut record).unwrap());
+    assert_eq!(record, vec!["1", "2", "a, b"]);
+    assert_eq!(reader.record_line(), 1);
+
+    assert!(reader.read_record(&mut record).unwrap());
+    assert_eq!(record, vec!["3", "say \"hi\"", "multi\nline"]);
+    assert_eq!(reader.record_line(), 3);
+
+    match reader.read_record(&mut record) {
+        Err(CsvError::UnexpectedQuote { line: 5 }) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+
+    assert!(reader.read_record(&mut record).unwrap());
+    assert_eq!(record, vec!["4", "", ""]);
+    assert!(!reader.read_record(&mut record).unwrap());
+
+    let mut reader = CsvReader::new(&b"\"open\n"[..]);
+    assert!(matches!(reader.read_record(&mut record), Err(CsvError::UnterminatedQuote { line: 1 })));
+    let mut reader = CsvReader::new(&b"\xff,1\n"[..]);
+    assert!(matches!(reader.read_record(&mut record), Err(CsvError::InvalidUtf8 { line: 1 })));
+}
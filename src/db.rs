@@ -0,0 +1,1406 @@
+use std::fs;
+use std::path::Path;
+use std::ops::Range;
+use std::ops::Deref;
+use std::borrow::{ Borrow, Cow };
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::net::IpAddr;
+#[cfg(feature = "embedded")]
+use std::sync::OnceLock;
+
+use crate::error::Error;
+use crate::format::{ self, Metadata };
+use crate::address::Address;
+use crate::country::COUNTRY_CODES_LEN;
+use crate::location::{ Location, Place };
+use crate::strings::StringTable;
+use crate::details::{ Details, RawDetails };
+use crate::geo::Coordinates;
+use crate::cidr::{ self, Cidr, CidrSet };
+use crate::embedding::{ Embedding, Normalization };
+use crate::special::Special;
+
+
+#[cfg(feature = "embedded")]
+pub static IP_DB: &[u8] = EMBEDDED_DB;
+
+#[cfg(feature = "embedded")]
+const EMBEDDED_DB: &[u8] = include_bytes!("ip_db.bin");
+
+// NOTE: 仓库里的 src/ip_db.bin 是 LFS 指针或旧格式，不检查的话要到第一次 `query()` 才会 panic。
+#[cfg(feature = "embedded")]
+const _: () = {
+    let version = format::FORMAT_VERSION.to_le_bytes();
+    let mut ok = EMBEDDED_DB.len() >= format::HEADER_LEN;
+    let mut idx = 0;
+    while ok && idx < format::MAGIC.len() + version.len() {
+        ok = if idx < format::MAGIC.len() {
+            EMBEDDED_DB[idx] == format::MAGIC[idx]
+        } else {
+            EMBEDDED_DB[idx] == version[idx - format::MAGIC.len()]
+        };
+        idx += 1;
+    }
+    assert!(ok, "src/ip_db.bin is not a current ip2location database, rebuild it with `ip2location build -o src/ip_db.bin`");
+};
+
+pub const V4_RECORD_SIZE: usize = 4 + 4 + 8;
+pub const V6_RECORD_SIZE: usize = 16 + 16 + 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<T: Sized> {
+    pub start: T,
+    pub end: T,
+    pub location_id: u64,
+}
+
+impl<T: Address> Record<T> {
+    pub const SIZE: usize = T::SIZE + T::SIZE + 8;
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::SIZE {
+            return None;
+        }
+        let start = T::from_le_slice(&bytes[0..]);
+        let end = T::from_le_slice(&bytes[T::SIZE..]);
+        let offset = T::SIZE + T::SIZE;
+        let location_id = u64::from_le_bytes([
+            bytes[offset],     bytes[offset + 1], bytes[offset + 2], bytes[offset + 3],
+            bytes[offset + 4], bytes[offset + 5], bytes[offset + 6], bytes[offset + 7],
+        ]);
+
+        Some(Record { start, end, location_id })
+    }
+
+    pub fn location(&self) -> Location {
+        Location(self.location_id)
+    }
+}
+
+enum Storage {
+    Bytes(Cow<'static, [u8]>),
+    #[cfg(feature = "mmap")]
+    Mmap(memmap2::Mmap),
+}
+
+impl Deref for Storage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Storage::Bytes(bytes) => bytes,
+            #[cfg(feature = "mmap")]
+            Storage::Mmap(mmap) => mmap,
+        }
+    }
+}
+
+/// The record zone an address is searched in.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Zone {
+    V4,
+    V6,
+}
+
+/// What is known about an address: the record it matched, its location and
+/// the special-purpose block it belongs to, any of which may be missing.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct LookupResult {
+    /// The address actually searched, after normalization.
+    pub addr: IpAddr,
+    /// The embedding `addr` was unwrapped from, if any.
+    pub embedding: Option<Embedding>,
+    pub zone: Zone,
+    /// Index of the matched record in its zone.
+    pub index: Option<usize>,
+    /// First and last address of the matched record.
+    pub range: Option<(IpAddr, IpAddr)>,
+    pub location: Option<Location>,
+    pub special: Option<Special>,
+}
+
+impl LookupResult {
+    /// Neither in the database nor special-purpose, an unknown public address.
+    pub fn is_unknown(&self) -> bool {
+        self.location.is_none() && self.special.is_none()
+    }
+
+    /// The largest prefix around `addr` that lies inside the matched range,
+    /// every address in it has the same answer.
+    pub fn prefix(&self) -> Option<(IpAddr, u8)> {
+        let (start, end) = self.range?;
+        match self.zone {
+            Zone::V4 => prefix_of::<u32>(start, end, self.addr),
+            Zone::V6 => prefix_of::<u128>(start, end, self.addr),
+        }
+    }
+}
+
+fn prefix_of<T: Address>(start: IpAddr, end: IpAddr, addr: IpAddr) -> Option<(IpAddr, u8)> {
+    let addr = T::from_ip(addr)?;
+    let mut cidrs = Vec::new();
+    cidr::split(T::from_ip(start)?, T::from_ip(end)?, &mut cidrs);
+    cidrs.into_iter()
+        .find(|cidr| cidr.contains(addr))
+        .map(|cidr| (cidr.network.to_ip(), cidr.prefix_len))
+}
+
+/// Key and record index zones, see `format::SECTION_V4_EYTZINGER`.
+type EytzingerRanges = (Range<usize>, Range<usize>);
+
+pub struct IpDb {
+    data: Storage,
+    metadata: Metadata,
+    v4_records_range: Range<usize>,
+    v6_records_range: Range<usize>,
+    v4_countries_range: Option<Range<usize>>,
+    v6_countries_range: Option<Range<usize>>,
+    v4_eytzinger_range: Option<EytzingerRanges>,
+    v6_eytzinger_range: Option<EytzingerRanges>,
+    v4_prefix_index_range: Option<Range<usize>>,
+    v6_prefix_index_range: Option<Range<usize>>,
+    provinces_range: Option<Range<usize>>,
+    cities_range: Option<Range<usize>>,
+    province_parents_range: Option<Range<usize>>,
+    city_parents_range: Option<Range<usize>>,
+    v4_details_range: Option<Range<usize>>,
+    v6_details_range: Option<Range<usize>>,
+    zip_codes_range: Option<Range<usize>>,
+    time_zones_range: Option<Range<usize>>,
+    normalization: Normalization,
+}
+
+impl IpDb {
+    /// Reads the whole database file into memory.
+    ///
+    /// Like every constructor this only checks the header and the section
+    /// table, run `validate` on files that may be corrupt.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let data = fs::read(path)?;
+        Self::from_bytes(data)
+    }
+
+    /// Maps the database file read-only, so the page cache is shared by every
+    /// process that opens the same file.
+    ///
+    /// The file must not be truncated or rewritten in place while it is mapped,
+    /// replace it with a rename instead.
+    #[cfg(feature = "mmap")]
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = fs::File::open(path)?;
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        // NOTE: 二分查找的访问是随机的，关闭预读。
+        #[cfg(unix)]
+        let _ = mmap.advise(memmap2::Advice::Random);
+
+        Self::from_storage(Storage::Mmap(mmap))
+    }
+
+    /// Accepts an owned `Vec<u8>` or a `&'static [u8]`, both without a copy.
+    /// Use `from_slice` for a borrowed buffer.
+    ///
+    /// The records are not scanned: a record with an unknown country is
+    /// treated as unassigned, other corruption may give wrong results.
+    /// `validate` catches both.
+    pub fn from_bytes<B: Into<Cow<'static, [u8]>>>(bytes: B) -> Result<Self, Error> {
+        Self::from_storage(Storage::Bytes(bytes.into()))
+    }
+
+    /// Copies `bytes`, e.g. a buffer that was just downloaded.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, Error> {
+        Self::from_bytes(bytes.to_vec())
+    }
+
+    fn from_storage(data: Storage) -> Result<Self, Error> {
+        let metadata = Metadata::parse(&data)?;
+        // NOTE: 未知的标志位可能改变记录的布局，不能当作没看到。
+        if metadata.flags & !(format::FLAG_COUNTRY_ONLY | format::FLAG_COMPACT) != 0
+            || (metadata.is_compact() && !metadata.is_country_only()) {
+            return Err(Error::InvalidHeader);
+        }
+        let (v4_record_size, v6_record_size) = if metadata.is_compact() {
+            (u32::SIZE + 1, u128::SIZE + 1)
+        } else {
+            (V4_RECORD_SIZE, V6_RECORD_SIZE)
+        };
+        if metadata.v4_record_size as usize != v4_record_size
+            || metadata.v6_record_size as usize != v6_record_size {
+            return Err(Error::InvalidHeader);
+        }
+
+        check_sections(&metadata, data.len())?;
+
+        let v4_records_range;
+        let v6_records_range;
+        let mut v4_countries_range = None;
+        let mut v6_countries_range = None;
+        if metadata.is_compact() {
+            v4_records_range = zone(&metadata, format::SECTION_V4_STARTS)?;
+            v6_records_range = zone(&metadata, format::SECTION_V6_STARTS)?;
+            let v4_countries = zone(&metadata, format::SECTION_V4_COUNTRIES)?;
+            let v6_countries = zone(&metadata, format::SECTION_V6_COUNTRIES)?;
+
+            check_zone(&v4_records_range, format::SECTION_V4_STARTS, u32::SIZE, metadata.v4_record_count)?;
+            check_zone(&v6_records_range, format::SECTION_V6_STARTS, u128::SIZE, metadata.v6_record_count)?;
+            check_zone(&v4_countries, format::SECTION_V4_COUNTRIES, 1, metadata.v4_record_count)?;
+            check_zone(&v6_countries, format::SECTION_V6_COUNTRIES, 1, metadata.v6_record_count)?;
+            v4_countries_range = Some(v4_countries);
+            v6_countries_range = Some(v6_countries);
+        } else {
+            v4_records_range = zone(&metadata, format::SECTION_V4_RECORDS)?;
+            v6_records_range = zone(&metadata, format::SECTION_V6_RECORDS)?;
+
+            check_zone(&v4_records_range, format::SECTION_V4_RECORDS, V4_RECORD_SIZE, metadata.v4_record_count)?;
+            check_zone(&v6_records_range, format::SECTION_V6_RECORDS, V6_RECORD_SIZE, metadata.v6_record_count)?;
+        }
+
+        let eytzinger_range = |kind: u32, index_kind: u32, key_size: usize, record_count: u32|
+                -> Result<Option<EytzingerRanges>, Error> {
+            let keys = match zone(&metadata, kind) {
+                Ok(keys) => keys,
+                Err(_) => return Ok(None),
+            };
+            let index = zone(&metadata, index_kind)?;
+            check_zone(&keys, kind, key_size, record_count)?;
+            check_zone(&index, index_kind, format::EYTZINGER_INDEX_SIZE, record_count)?;
+            Ok(Some((keys, index)))
+        };
+        let v4_eytzinger_range = eytzinger_range(format::SECTION_V4_EYTZINGER, format::SECTION_V4_EYTZINGER_INDEX,
+            u32::SIZE, metadata.v4_record_count)?;
+        let v6_eytzinger_range = eytzinger_range(format::SECTION_V6_EYTZINGER, format::SECTION_V6_EYTZINGER_INDEX,
+            u128::SIZE, metadata.v6_record_count)?;
+
+        let v4_prefix_index_range = zone(&metadata, format::SECTION_V4_PREFIX_INDEX).ok();
+        let v6_prefix_index_range = zone(&metadata, format::SECTION_V6_PREFIX_INDEX).ok();
+        if let Some(range) = v4_prefix_index_range.as_ref() {
+            check_zone(range, format::SECTION_V4_PREFIX_INDEX, 4, format::V4_PREFIX_INDEX_LEN as u32)?;
+        }
+        if let Some(range) = v6_prefix_index_range.as_ref() {
+            if range.is_empty() || !range.len().is_multiple_of(format::V6_PREFIX_ENTRY_SIZE) {
+                return Err(Error::MisalignedZone(format::SECTION_V6_PREFIX_INDEX));
+            }
+        }
+
+        let string_table_range = |kind: u32| -> Result<Option<Range<usize>>, Error> {
+            match zone(&metadata, kind).ok() {
+                Some(range) => {
+                    StringTable::parse(&data[range.clone()], kind)?;
+                    Ok(Some(range))
+                },
+                None => Ok(None),
+            }
+        };
+        let provinces_range = string_table_range(format::SECTION_PROVINCES)?;
+        let cities_range = string_table_range(format::SECTION_CITIES)?;
+        let zip_codes_range = string_table_range(format::SECTION_ZIP_CODES)?;
+        let time_zones_range = string_table_range(format::SECTION_TIME_ZONES)?;
+
+        let parents_range = |table: &Option<Range<usize>>, table_kind: u32, kind: u32, size: usize|
+                -> Result<Option<Range<usize>>, Error> {
+            match table {
+                Some(table) => {
+                    let len = StringTable::parse(&data[table.clone()], table_kind)?.len();
+                    let range = zone(&metadata, kind)?;
+                    check_zone(&range, kind, size, len as u32)?;
+                    Ok(Some(range))
+                },
+                None => Ok(None),
+            }
+        };
+        let province_parents_range = parents_range(&provinces_range, format::SECTION_PROVINCES,
+            format::SECTION_PROVINCE_PARENTS, format::PROVINCE_PARENT_SIZE)?;
+        let city_parents_range = parents_range(&cities_range, format::SECTION_CITIES,
+            format::SECTION_CITY_PARENTS, format::CITY_PARENT_SIZE)?;
+
+        let v4_details_range = zone(&metadata, format::SECTION_V4_DETAILS).ok();
+        let v6_details_range = zone(&metadata, format::SECTION_V6_DETAILS).ok();
+        if let Some(range) = v4_details_range.as_ref() {
+            check_zone(range, format::SECTION_V4_DETAILS, format::DETAILS_SIZE, metadata.v4_record_count)?;
+        }
+        if let Some(range) = v6_details_range.as_ref() {
+            check_zone(range, format::SECTION_V6_DETAILS, format::DETAILS_SIZE, metadata.v6_record_count)?;
+        }
+
+        Ok(Self {
+            data, metadata,
+            v4_records_range, v6_records_range,
+            v4_countries_range, v6_countries_range,
+            v4_eytzinger_range, v6_eytzinger_range,
+            v4_prefix_index_range, v6_prefix_index_range,
+            provinces_range, cities_range,
+            province_parents_range, city_parents_range,
+            v4_details_range, v6_details_range,
+            zip_codes_range, time_zones_range,
+            normalization: Normalization::default(),
+        })
+    }
+
+    /// Parses the `src/ip_db.bin` bundled at compile time.
+    #[cfg(feature = "embedded")]
+    pub fn embedded() -> Result<Self, Error> {
+        Self::from_bytes(IP_DB)
+    }
+
+    /// Walks every record and checks that each zone is sorted, free of
+    /// overlaps and only refers to known countries, provinces and cities.
+    ///
+    /// The constructors only check the header and the section table, this is
+    /// a full scan of the database.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.provinces().validate_by_key(format::SECTION_PROVINCES, |idx| self.province_parent(idx))?;
+        self.cities().validate_by_key(format::SECTION_CITIES, |idx| self.city_parent(idx))?;
+        self.validate_parents()?;
+
+        let (v4_section, v6_section) = if self.metadata.is_compact() {
+            validate_compact(&self.v4_records(), format::SECTION_V4_STARTS)?;
+            validate_compact(&self.v6_records(), format::SECTION_V6_STARTS)?;
+            (format::SECTION_V4_STARTS, format::SECTION_V6_STARTS)
+        } else {
+            (format::SECTION_V4_RECORDS, format::SECTION_V6_RECORDS)
+        };
+        self.validate_records(&self.v4_records(), v4_section)?;
+        self.validate_records(&self.v6_records(), v6_section)?;
+        validate_eytzinger(&self.v4_records(), format::SECTION_V4_EYTZINGER)?;
+        validate_eytzinger(&self.v6_records(), format::SECTION_V6_EYTZINGER)?;
+        validate_prefix_index(&self.v4_records(), format::SECTION_V4_PREFIX_INDEX)?;
+        validate_prefix_index(&self.v6_records(), format::SECTION_V6_PREFIX_INDEX)?;
+
+        let zip_codes = self.zip_codes();
+        let time_zones = self.time_zones();
+        zip_codes.validate(format::SECTION_ZIP_CODES)?;
+        time_zones.validate(format::SECTION_TIME_ZONES)?;
+
+        let limits = (zip_codes.len(), time_zones.len());
+        let zones = [
+            (&self.v4_details_range, format::SECTION_V4_DETAILS),
+            (&self.v6_details_range, format::SECTION_V6_DETAILS),
+        ];
+        for (range, section) in zones.iter() {
+            if let Some(range) = range {
+                validate_details(&self.data[range.clone()], *section, limits)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_parents(&self) -> Result<(), Error> {
+        for index in 0..self.provinces().len() {
+            let country = self.province_parent(index).ok_or(Error::MissingSection(format::SECTION_PROVINCE_PARENTS))?;
+            if country as usize >= COUNTRY_CODES_LEN {
+                return Err(Error::InvalidCountry { section: format::SECTION_PROVINCE_PARENTS, index });
+            }
+        }
+        for index in 0..self.cities().len() {
+            let (country, province) = self.city_parent(index).ok_or(Error::MissingSection(format::SECTION_CITY_PARENTS))?;
+            if country as usize >= COUNTRY_CODES_LEN {
+                return Err(Error::InvalidCountry { section: format::SECTION_CITY_PARENTS, index });
+            }
+            if province != u16::MAX && self.province_parent(province as usize) != Some(country) {
+                return Err(Error::InvalidLocation { section: format::SECTION_CITY_PARENTS, index });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every record must refer to known countries, provinces and cities, and
+    /// the province and city must belong to the record's country.
+    fn validate_records<T: Address>(&self, records: &Records<'_, T>, section: u32) -> Result<(), Error> {
+        let mut prev: Option<Record<T>> = None;
+        for (index, record) in (0..records.len).filter_map(|idx| records.record(idx).map(|record| (idx, record))) {
+            if record.start > record.end {
+                return Err(Error::InvalidRange { section, index });
+            }
+            if let Some(prev) = prev {
+                if record.start <= prev.start {
+                    return Err(Error::UnsortedRecords { section, index });
+                }
+                if record.start <= prev.end {
+                    return Err(Error::OverlappingRanges { section, index });
+                }
+            }
+
+            let location = record.location();
+            let country = location.country_index();
+            if country as usize >= COUNTRY_CODES_LEN {
+                return Err(Error::InvalidCountry { section, index });
+            }
+            let province = location.province().map(|p| p.index()).unwrap_or(u16::MAX);
+            let province_ok = province == u16::MAX || self.province_parent(province as usize) == Some(country);
+            let city_ok = location.city()
+                .map(|c| self.city_parent(c.index() as usize) == Some((country, province)))
+                .unwrap_or(true);
+            if !province_ok || !city_ok {
+                return Err(Error::InvalidLocation { section, index });
+            }
+
+            prev = Some(record);
+        }
+
+        Ok(())
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn v4_records(&self) -> V4Records<'_> {
+        self.records(&self.v4_records_range, &self.v4_countries_range,
+                     &self.v4_eytzinger_range, &self.v4_prefix_index_range)
+    }
+
+    pub fn v6_records(&self) -> V6Records<'_> {
+        self.records(&self.v6_records_range, &self.v6_countries_range,
+                     &self.v6_eytzinger_range, &self.v6_prefix_index_range)
+    }
+
+    fn records<T: Address>(&self, range: &Range<usize>, countries: &Option<Range<usize>>,
+                           eytzinger: &Option<EytzingerRanges>,
+                           prefix_index: &Option<Range<usize>>) -> Records<'_, T> {
+        let mut records = match countries {
+            Some(countries) => Records::compact(&self.data[range.clone()], &self.data[countries.clone()]),
+            None => Records::new(&self.data[range.clone()]),
+        };
+        if let Some((keys, index)) = eytzinger {
+            records.eytzinger = Some((&self.data[keys.clone()], &self.data[index.clone()]));
+        }
+        records.prefix_index = prefix_index.clone().map(|range| &self.data[range]);
+        records
+    }
+
+    pub fn provinces(&self) -> StringTable<'_> {
+        self.string_table(&self.provinces_range, format::SECTION_PROVINCES)
+    }
+
+    pub fn cities(&self) -> StringTable<'_> {
+        self.string_table(&self.cities_range, format::SECTION_CITIES)
+    }
+
+    pub fn zip_codes(&self) -> StringTable<'_> {
+        self.string_table(&self.zip_codes_range, format::SECTION_ZIP_CODES)
+    }
+
+    pub fn time_zones(&self) -> StringTable<'_> {
+        self.string_table(&self.time_zones_range, format::SECTION_TIME_ZONES)
+    }
+
+    /// Country index of the province at `idx`.
+    pub(crate) fn province_parent(&self, idx: usize) -> Option<u8> {
+        let range = self.province_parents_range.as_ref()?;
+        self.data[range.clone()].get(idx).cloned()
+    }
+
+    /// Country index and province index (`u16::MAX` if unknown) of the city at `idx`.
+    pub(crate) fn city_parent(&self, idx: usize) -> Option<(u8, u16)> {
+        let range = self.city_parents_range.as_ref()?;
+        let offset = idx.checked_mul(format::CITY_PARENT_SIZE)?;
+        let bytes = self.data[range.clone()].get(offset..offset + format::CITY_PARENT_SIZE)?;
+        Some((bytes[2], u16::from_le_bytes([bytes[0], bytes[1]])))
+    }
+
+    fn string_table(&self, range: &Option<Range<usize>>, section: u32) -> StringTable<'_> {
+        // NOTE: 构造时已经检查过，这里不会失败。
+        range.clone()
+            .and_then(|range| StringTable::parse(&self.data[range], section).ok())
+            .unwrap_or_else(StringTable::empty)
+    }
+
+    pub fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    /// Chooses which IPv6 forms embedding an IPv4 address are searched in
+    /// the v4 zone, all of them by default.
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        self.normalization = normalization;
+    }
+
+    /// The address `query` and `details` actually search for `addr`, and the
+    /// embedding it was unwrapped from.
+    pub fn normalize(&self, addr: &IpAddr) -> (IpAddr, Option<Embedding>) {
+        self.normalization.normalize(*addr)
+    }
+
+    pub fn query(&self, addr: &IpAddr) -> Option<Location> {
+        match self.normalize(addr).0 {
+            IpAddr::V4(v4_addr) => {
+                let v4_number = u32::from(v4_addr);
+                self.v4_records().binary_search(v4_number)
+                    .map(|record| record.location() )
+            },
+            IpAddr::V6(v6_addr) => {
+                let v6_number = u128::from(v6_addr);
+                self.v6_records().binary_search(v6_number)
+                    .map(|record| record.location() )
+            },
+        }
+    }
+
+    /// Like `query`, and also reports the matched record and classifies the
+    /// address against the IANA special-purpose registries, so `10.0.0.1` is
+    /// reported as private rather than just missing.
+    pub fn lookup(&self, addr: &IpAddr) -> LookupResult {
+        let (addr, embedding) = self.normalize(addr);
+        let index = match addr {
+            IpAddr::V4(v4_addr) => self.v4_records().search(u32::from(v4_addr)),
+            IpAddr::V6(v6_addr) => self.v6_records().search(u128::from(v6_addr)),
+        };
+        self.lookup_result(addr, embedding, index)
+    }
+
+    /// `lookup` for many addresses at once, results are in input order.
+    ///
+    /// The addresses are sorted (unless they already are) and each zone is
+    /// walked once with a galloping search, so runs of addresses in the same
+    /// or nearby ranges are much cheaper than separate lookups.
+    pub fn lookup_batch<I>(&self, addrs: I) -> Vec<LookupResult>
+        where I: IntoIterator,
+              I::Item: Borrow<IpAddr> {
+        let addrs = addrs.into_iter().map(|addr| self.normalize(addr.borrow())).collect::<Vec<_>>();
+
+        let mut v4_keys = Vec::new();
+        let mut v6_keys = Vec::new();
+        for (i, (addr, _)) in addrs.iter().enumerate() {
+            match addr {
+                IpAddr::V4(v4_addr) => v4_keys.push((u32::from(*v4_addr), i)),
+                IpAddr::V6(v6_addr) => v6_keys.push((u128::from(*v6_addr), i)),
+            }
+        }
+
+        let mut indexes = vec![None; addrs.len()];
+        search_sorted(self.v4_records(), &mut v4_keys, &mut indexes);
+        search_sorted(self.v6_records(), &mut v6_keys, &mut indexes);
+
+        addrs.into_iter()
+            .zip(indexes)
+            .map(|((addr, embedding), index)| self.lookup_result(addr, embedding, index))
+            .collect()
+    }
+
+    fn lookup_result(&self, addr: IpAddr, embedding: Option<Embedding>, index: Option<usize>) -> LookupResult {
+        let (zone, matched) = match addr {
+            IpAddr::V4(_) => (Zone::V4, index.and_then(|idx| matched(self.v4_records(), idx))),
+            IpAddr::V6(_) => (Zone::V6, index.and_then(|idx| matched(self.v6_records(), idx))),
+        };
+
+        LookupResult {
+            addr, embedding, zone, index,
+            range: matched.map(|(range, _)| range),
+            location: matched.map(|(_, location)| location),
+            special: Special::classify(&addr),
+        }
+    }
+
+    /// Every range whose location lies in `place`, IPv4 first, both in
+    /// ascending order.
+    pub fn ranges<P: Into<Place>>(&self, place: P) -> impl Iterator<Item = (IpAddr, IpAddr)> + '_ {
+        let place = place.into();
+        let v4 = self.v4_records().matching(place).map(|record| (record.start.to_ip(), record.end.to_ip()));
+        let v6 = self.v6_records().matching(place).map(|record| (record.start.to_ip(), record.end.to_ip()));
+        v4.chain(v6)
+    }
+
+    /// The shortest list of IPv4 prefixes covering every range in `place`.
+    pub fn v4_cidrs<P: Into<Place>>(&self, place: P) -> Vec<Cidr<u32>> {
+        cidr::to_cidrs(self.v4_records().matching(place).map(|record| (record.start, record.end)))
+    }
+
+    /// The shortest list of IPv6 prefixes covering every range in `place`.
+    pub fn v6_cidrs<P: Into<Place>>(&self, place: P) -> Vec<Cidr<u128>> {
+        cidr::to_cidrs(self.v6_records().matching(place).map(|record| (record.start, record.end)))
+    }
+
+    /// Prefixes of both families covering every range in `places`.
+    pub fn cidr_set(&self, places: &[Place]) -> CidrSet {
+        CidrSet::from_ranges(
+            places.iter().flat_map(|place| self.v4_records().matching(*place)).map(|record| (record.start, record.end)),
+            places.iter().flat_map(|place| self.v6_records().matching(*place)).map(|record| (record.start, record.end)),
+        )
+    }
+
+    /// Coordinates, ZIP code and time zone of the range containing `addr`.
+    ///
+    /// Returns `None` if `addr` is not in the database, or the database was
+    /// built from a tier without these fields (DB1, DB3).
+    pub fn details(&self, addr: &IpAddr) -> Option<Details<'_>> {
+        match self.normalize(addr).0 {
+            IpAddr::V4(v4_addr) => self.details_at(Zone::V4, self.v4_records().search(u32::from(v4_addr))?),
+            IpAddr::V6(v6_addr) => self.details_at(Zone::V6, self.v6_records().search(u128::from(v6_addr))?),
+        }
+    }
+
+    /// Details of the record at `index` in `zone`, e.g. the one a
+    /// `LookupResult` matched, without searching again.
+    pub fn details_at(&self, zone: Zone, index: usize) -> Option<Details<'_>> {
+        let range = match zone {
+            Zone::V4 => self.v4_details_range.as_ref()?,
+            Zone::V6 => self.v6_details_range.as_ref()?,
+        };
+        let offset = index.checked_mul(format::DETAILS_SIZE)?;
+        let raw = RawDetails::from_bytes(self.data[range.clone()].get(offset..)?)?;
+
+        let tier = self.metadata.tier;
+        Some(Details {
+            latitude: if tier.has_coordinates() { Some(raw.latitude) } else { None },
+            longitude: if tier.has_coordinates() { Some(raw.longitude) } else { None },
+            zip_code: self.zip_codes().get(raw.zip_code as usize),
+            time_zone: self.time_zones().get(raw.time_zone as usize),
+        })
+    }
+
+    /// Requires a DB5 or higher tier database.
+    pub fn coordinates(&self, addr: &IpAddr) -> Option<Coordinates> {
+        self.details(addr)?.coordinates()
+    }
+
+    /// Distance between the locations of two addresses.
+    pub fn distance_km(&self, a: &IpAddr, b: &IpAddr) -> Option<f64> {
+        Some(self.coordinates(a)?.distance_km(&self.coordinates(b)?))
+    }
+
+    /// Distance between the location of `addr` and `point`.
+    pub fn distance_to_km(&self, addr: &IpAddr, point: &Coordinates) -> Option<f64> {
+        Some(self.coordinates(addr)?.distance_km(point))
+    }
+
+    /// Picks the candidate closest to `addr`, e.g. the nearest exit node.
+    ///
+    /// Candidates without coordinates are skipped. Returns the index into
+    /// `candidates` and the distance.
+    pub fn nearest(&self, addr: &IpAddr, candidates: &[IpAddr]) -> Option<(usize, f64)> {
+        let origin = self.coordinates(addr)?;
+        candidates.iter()
+            .enumerate()
+            .filter_map(|(idx, candidate)| Some((idx, self.distance_to_km(candidate, &origin)?)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+fn matched<T: Address>(records: Records<'_, T>, idx: usize) -> Option<((IpAddr, IpAddr), Location)> {
+    let record = records.get(idx)?;
+    Some(((record.start.to_ip(), record.end.to_ip()), record.location()))
+}
+
+/// Hints the CPU to load the cache line holding `bytes[offset]`, does
+/// nothing past the end.
+#[inline(always)]
+fn prefetch(bytes: &[u8], offset: usize) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if let Some(byte) = bytes.get(offset) {
+            use std::arch::x86_64::{ _mm_prefetch, _MM_HINT_T0 };
+            unsafe { _mm_prefetch(byte as *const u8 as *const i8, _MM_HINT_T0) };
+        }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = (bytes, offset);
+}
+
+/// Fills `indexes[i]` for every `(key, i)` in `keys`.
+fn search_sorted<T: Address>(records: Records<'_, T>, keys: &mut [(T, usize)], indexes: &mut [Option<usize>]) {
+    // NOTE: 日志里的地址通常已经大致有序，只有乱序时才排序。
+    if !keys.windows(2).all(|pair| pair[0] <= pair[1]) {
+        keys.sort_unstable();
+    }
+
+    let mut from = 0;
+    for &(x, i) in keys.iter() {
+        indexes[i] = match records.search_from(x, from) {
+            Ok(idx) => {
+                from = idx;
+                Some(idx)
+            },
+            Err(idx) => {
+                from = idx;
+                None
+            },
+        };
+    }
+}
+
+fn check_sections(metadata: &Metadata, data_len: usize) -> Result<(), Error> {
+    let mut sections = metadata.sections.clone();
+    sections.sort_by_key(|section| section.offset);
+
+    let mut prev: Option<(u32, usize)> = None;
+    for section in sections.iter() {
+        let start = section.offset as usize;
+        let end = start + section.len as usize;
+        if end > data_len {
+            return Err(Error::Truncated);
+        }
+        if start < metadata.header_len() {
+            return Err(Error::OverlappingZones(0, section.kind));
+        }
+        if let Some((prev_kind, prev_end)) = prev {
+            if start < prev_end {
+                return Err(Error::OverlappingZones(prev_kind, section.kind));
+            }
+        }
+        prev = Some((section.kind, end));
+    }
+
+    Ok(())
+}
+
+fn zone(metadata: &Metadata, kind: u32) -> Result<Range<usize>, Error> {
+    let section = metadata.section(kind).ok_or(Error::MissingSection(kind))?;
+    let start = section.offset as usize;
+    let end = start + section.len as usize;
+
+    Ok(start..end)
+}
+
+fn check_zone(zone: &Range<usize>, kind: u32, record_size: usize, record_count: u32) -> Result<(), Error> {
+    if !zone.len().is_multiple_of(record_size) || zone.len() / record_size != record_count as usize {
+        return Err(Error::MisalignedZone(kind));
+    }
+
+    Ok(())
+}
+
+/// The starts must begin at 0 and increase, every country must be known or
+/// `COMPACT_GAP`.
+fn validate_compact<T: Address>(records: &Records<'_, T>, section: u32) -> Result<(), Error> {
+    let countries = records.countries.unwrap_or(&[]);
+    for (index, &country) in countries.iter().enumerate() {
+        let start = records.start(index);
+        if index == 0 && start.to_u128() != 0 {
+            return Err(Error::InvalidRange { section, index });
+        }
+        if index > 0 && start <= records.start(index - 1) {
+            return Err(Error::UnsortedRecords { section, index });
+        }
+        if country != format::COMPACT_GAP && country as usize >= COUNTRY_CODES_LEN {
+            return Err(Error::InvalidCountry { section, index });
+        }
+    }
+
+    Ok(())
+}
+
+/// Every key must be the start of the record it points to, and an in-order
+/// walk of the tree must visit the records in order.
+fn validate_eytzinger<T: Address>(records: &Records<'_, T>, section: u32) -> Result<(), Error> {
+    if records.eytzinger.is_none() {
+        return Ok(());
+    }
+
+    let mut expected = 0;
+    let mut stack = Vec::new();
+    let mut k = 1;
+    loop {
+        while k <= records.len {
+            stack.push(k);
+            k *= 2;
+        }
+        let pos = match stack.pop() {
+            Some(pos) => pos,
+            None => break,
+        };
+        let index = records.eytzinger_index(pos);
+        if index != expected {
+            return Err(Error::UnsortedRecords { section, index: pos - 1 });
+        }
+        if records.eytzinger_key(pos) != records.start(index) {
+            return Err(Error::InvalidRange { section, index: pos - 1 });
+        }
+        expected += 1;
+        k = pos * 2 + 1;
+    }
+
+    Ok(())
+}
+
+/// Every entry must point at the first record ending at or after its
+/// prefix, v6 prefixes must start at 0 and increase.
+fn validate_prefix_index<T: Address>(records: &Records<'_, T>, section: u32) -> Result<(), Error> {
+    if records.prefix_index.is_none() {
+        return Ok(());
+    }
+
+    let shift = T::BITS - records.prefix_bits();
+    let entries = records.prefix_entries();
+    let mut prev = None;
+    for i in 0..entries {
+        let (prefix, first) = records.prefix_entry(i);
+        let ok = match prev {
+            None => prefix == 0,
+            Some(prev) => prefix > prev,
+        };
+        if !ok {
+            return Err(Error::UnsortedRecords { section, index: i });
+        }
+        prev = Some(prefix);
+
+        let expected = if (prefix as u128) < 1 << records.prefix_bits() {
+            records.partition_end(T::from_u128((prefix as u128) << shift), 0, records.len)
+        } else {
+            records.len
+        };
+        if first != expected {
+            return Err(Error::InvalidRange { section, index: i });
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_details(zone: &[u8], section: u32, (zip_codes_len, time_zones_len): (usize, usize)) -> Result<(), Error> {
+    for (index, bytes) in zone.chunks_exact(format::DETAILS_SIZE).enumerate() {
+        let raw = RawDetails::from_bytes(bytes).ok_or(Error::MisalignedZone(section))?;
+        let zip_code_ok = raw.zip_code == u32::MAX || (raw.zip_code as usize) < zip_codes_len;
+        let time_zone_ok = raw.time_zone == u16::MAX || (raw.time_zone as usize) < time_zones_len;
+        if !zip_code_ok || !time_zone_ok {
+            return Err(Error::InvalidLocation { section, index });
+        }
+    }
+
+    Ok(())
+}
+
+pub type V4Records<'a> = Records<'a, u32>;
+pub type V6Records<'a> = Records<'a, u128>;
+
+/// A zone of fixed-size records sorted by `start`.
+///
+/// In compact databases the zone holds the starts only, every record ends
+/// where the next one starts and unassigned ranges are kept as gaps.
+#[derive(Clone, Copy)]
+pub struct Records<'a, T> {
+    bytes: &'a [u8],
+    /// Compact only, one country index per start.
+    countries: Option<&'a [u8]>,
+    /// Optional search keys and record indexes in Eytzinger order.
+    eytzinger: Option<(&'a [u8], &'a [u8])>,
+    /// Optional, see `format::SECTION_V4_PREFIX_INDEX`.
+    prefix_index: Option<&'a [u8]>,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<'a, T: Address> Records<'a, T> {
+    fn new(bytes: &'a [u8]) -> Self {
+        let len = bytes.len() / Record::<T>::SIZE;
+        Self { bytes, countries: None, eytzinger: None, prefix_index: None, len, _marker: PhantomData }
+    }
+
+    fn compact(starts: &'a [u8], countries: &'a [u8]) -> Self {
+        Self {
+            bytes: starts,
+            countries: Some(countries),
+            eytzinger: None,
+            prefix_index: None,
+            len: countries.len(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Number of records, in compact databases including the gaps that
+    /// `get` returns `None` for.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The record at `idx`, `None` for gaps and for records with an unknown
+    /// country, which only a corrupt database has.
+    pub fn get(&self, idx: usize) -> Option<Record<T>> {
+        // NOTE: 构造函数不扫描记录，在这里挡掉损坏的国家编号，否则 Location::country 会 panic。
+        self.record(idx).filter(|record| (record.location().country_index() as usize) < COUNTRY_CODES_LEN)
+    }
+
+    fn record(&self, idx: usize) -> Option<Record<T>> {
+        if idx >= self.len {
+            return None;
+        }
+        if let Some(countries) = self.countries {
+            let country = countries[idx];
+            if country == format::COMPACT_GAP {
+                return None;
+            }
+            let end = if idx + 1 < self.len {
+                T::from_u128(self.start(idx + 1).to_u128().wrapping_sub(1))
+            } else {
+                T::from_u128(u128::MAX)
+            };
+            let location = Location::new(country, u16::MAX, u32::MAX);
+            return Some(Record { start: self.start(idx), end, location_id: location.0 });
+        }
+
+        let offset = idx * Record::<T>::SIZE;
+        let bytes = &self.bytes[offset..offset + Record::<T>::SIZE];
+
+        Record::<T>::from_bytes(bytes)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Record<T>> + 'a {
+        let records = *self;
+        (0..self.len).filter_map(move |idx| records.get(idx))
+    }
+
+    /// Records whose location lies in `place`, a full scan.
+    pub fn matching<P: Into<Place>>(&self, place: P) -> impl Iterator<Item = Record<T>> + 'a {
+        let place = place.into();
+        self.iter().filter(move |record| place.contains(&record.location()))
+    }
+
+    pub fn binary_search(&self, x: T) -> Option<Record<T>> {
+        self.search(x).and_then(|idx| self.get(idx))
+    }
+
+    /// First address covered by the record (or compact gap) at `idx`.
+    #[inline]
+    fn start(&self, idx: usize) -> T {
+        if self.countries.is_some() {
+            return T::from_le_slice(&self.bytes[idx * T::SIZE..]);
+        }
+        T::from_le_slice(&self.bytes[idx * Record::<T>::SIZE..])
+    }
+
+    /// Key at the 1-based Eytzinger position `pos`.
+    #[inline]
+    fn eytzinger_key(&self, pos: usize) -> T {
+        let (keys, _) = self.eytzinger.unwrap_or((&[], &[]));
+        T::from_le_slice(&keys[(pos - 1) * T::SIZE..])
+    }
+
+    /// Record index at the 1-based Eytzinger position `pos`.
+    #[inline]
+    fn eytzinger_index(&self, pos: usize) -> usize {
+        let (_, index) = self.eytzinger.unwrap_or((&[], &[]));
+        let offset = (pos - 1) * format::EYTZINGER_INDEX_SIZE;
+        u32::from_le_bytes([index[offset], index[offset + 1], index[offset + 2], index[offset + 3]]) as usize
+    }
+
+    /// The last record starting at or before `x`, found by descending the
+    /// Eytzinger tree.
+    fn eytzinger_search(&self, x: T) -> Option<usize> {
+        let keys = self.eytzinger.map_or(&[][..], |(keys, _)| keys);
+        let mut k = 1;
+        while k <= self.len {
+            // NOTE: 提前取四层之后的节点，它们在同一段连续内存里。
+            prefetch(keys, (16 * k - 1) * T::SIZE);
+            k = 2 * k + (self.eytzinger_key(k) <= x) as usize;
+        }
+        // NOTE: 去掉末尾连续的 1 和其后的一个 0，剩下的就是第一个 key > x 的位置。
+        k >>= k.trailing_ones() + 1;
+        let upper = if k == 0 { self.len } else { self.eytzinger_index(k) };
+
+        let idx = upper.checked_sub(1)?;
+        if let Some(countries) = self.countries {
+            // NOTE: 紧凑格式没有空隙，只需看是不是未分配的段。
+            return match countries.get(idx) {
+                Some(&country) if country != format::COMPACT_GAP => Some(idx),
+                _ => None,
+            };
+        }
+        let record = self.get(idx)?;
+        if x <= record.end {
+            Some(idx)
+        } else {
+            None
+        }
+    }
+
+    /// Last address covered by the record (or compact gap) at `idx`.
+    #[inline]
+    fn end(&self, idx: usize) -> T {
+        if self.countries.is_some() {
+            return if idx + 1 < self.len {
+                T::from_u128(self.start(idx + 1).to_u128().wrapping_sub(1))
+            } else {
+                T::from_u128(u128::MAX)
+            };
+        }
+        T::from_le_slice(&self.bytes[idx * Record::<T>::SIZE + T::SIZE..])
+    }
+
+    /// Galloping search for `x`, starting at record `from`, for keys that
+    /// arrive in ascending order.
+    ///
+    /// Returns `Ok(idx)` with the record containing `x`, `Err(idx)` if there
+    /// is none. Either way `idx` is where the search for the next larger key
+    /// should start, so a sorted run of `n` keys costs `O(n + log distance)`.
+    pub fn search_from(&self, x: T, from: usize) -> Result<usize, usize> {
+        // NOTE: 记录有序且不重叠，end 单调递增，找第一个 end >= x 的记录。
+        let mut lo = from.min(self.len);
+        let mut hi = lo;
+        let mut step = 1;
+        while hi < self.len && self.end(hi) < x {
+            lo = hi + 1;
+            hi += step;
+            step *= 2;
+        }
+        let idx = self.partition_end(x, lo, hi.min(self.len));
+
+        match self.get(idx) {
+            Some(record) if record.start <= x => Ok(idx),
+            _ => Err(idx),
+        }
+    }
+
+    /// First index in `lo..hi` whose record ends at or after `x`, `hi` if none.
+    fn partition_end(&self, x: T, mut lo: usize, mut hi: usize) -> usize {
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.end(mid) < x {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+
+    /// Prefix length the index is keyed by, /16 for v4 and /32 for v6.
+    fn prefix_bits(&self) -> u32 {
+        if T::BITS == 32 { 16 } else { 32 }
+    }
+
+    fn prefix_entries(&self) -> usize {
+        let index = self.prefix_index.unwrap_or(&[]);
+        if T::BITS == 32 {
+            index.len() / 4
+        } else {
+            index.len() / format::V6_PREFIX_ENTRY_SIZE
+        }
+    }
+
+    /// Prefix and first record index of entry `i`, the v4 index is dense so
+    /// its prefix is `i`.
+    #[inline]
+    fn prefix_entry(&self, i: usize) -> (u32, usize) {
+        let index = self.prefix_index.unwrap_or(&[]);
+        let u32_at = |offset: usize| u32::from_le_slice(&index[offset..]);
+        if T::BITS == 32 {
+            (i as u32, u32_at(i * 4) as usize)
+        } else {
+            let offset = i * format::V6_PREFIX_ENTRY_SIZE;
+            (u32_at(offset), u32_at(offset + 4) as usize)
+        }
+    }
+
+    /// Records `lo..hi` that may contain `x` according to the prefix index.
+    fn prefix_bounds(&self, x: T) -> Option<(usize, usize)> {
+        self.prefix_index?;
+        let prefix = (x.to_u128() >> (T::BITS - self.prefix_bits())) as u32;
+        let (lo, next) = if T::BITS == 32 {
+            (self.prefix_entry(prefix as usize).1, self.prefix_entry(prefix as usize + 1).1)
+        } else {
+            // NOTE: 稀疏表很小，常驻缓存，先在表里找最后一个不大于该前缀的项。
+            let mut lo = 0;
+            let mut hi = self.prefix_entries();
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                if self.prefix_entry(mid).0 <= prefix {
+                    lo = mid + 1;
+                } else {
+                    hi = mid;
+                }
+            }
+            let first = self.prefix_entry(lo.checked_sub(1)?).1;
+            let next = if lo < self.prefix_entries() { self.prefix_entry(lo).1 } else { self.len };
+            (first, next)
+        };
+
+        // NOTE: 下一个前缀的第一条记录可能跨过边界，也要包含进来。
+        let hi = next.saturating_add(1).min(self.len);
+        Some((lo.min(hi), hi))
+    }
+
+    /// Index of the record containing `x`.
+    pub fn search(&self, x: T) -> Option<usize> {
+        if let Some((lo, hi)) = self.prefix_bounds(x) {
+            let idx = self.partition_end(x, lo, hi);
+            return match self.get(idx) {
+                Some(record) if record.start <= x && x <= record.end => Some(idx),
+                _ => None,
+            };
+        }
+        if self.eytzinger.is_some() {
+            return self.eytzinger_search(x);
+        }
+        if self.countries.is_some() {
+            // NOTE: 紧凑格式覆盖整个地址空间，找最后一个 start <= x 的段即可。
+            let mut lo = 0usize;
+            let mut hi = self.len;
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                if self.start(mid) <= x {
+                    lo = mid + 1;
+                } else {
+                    hi = mid;
+                }
+            }
+            return lo.checked_sub(1).filter(|&idx| self.get(idx).is_some());
+        }
+
+        let s = self;
+
+        let mut size = s.len();
+        if size == 0 {
+            return None;
+        }
+
+        let mut base = 0usize;
+        while size > 1 {
+            let half = size / 2;
+            let mid = base + half;
+            // mid is always in [0, size), that means mid is >= 0 and < size.
+            // mid >= 0: by definition
+            // mid < size: mid = size / 2 + size / 4 + size / 8 ...
+            let item = s.record(mid).unwrap();
+            let cmp = {
+                if x >= item.start && x <= item.end {
+                    Ordering::Equal
+                } else if x > item.end {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                }
+            };
+            base = if cmp == Ordering::Greater { base } else { mid };
+            size -= half;
+        }
+        // base is always in [0, size) because base <= mid.
+        let item = s.get(base)?;
+        if x >= item.start && x <= item.end {
+            Some(base)
+        } else {
+            None
+        }
+    }
+}
+
+/// Queries the embedded database, which is parsed once on first use.
+///
+/// Panics with the parse error if the bundled `src/ip_db.bin` is not a valid
+/// database, use `IpDb::embedded` to handle that case.
+#[cfg(feature = "embedded")]
+pub fn query(addr: &IpAddr) -> Option<Location> {
+    static DB: OnceLock<IpDb> = OnceLock::new();
+    DB.get_or_init(|| IpDb::embedded().unwrap_or_else(|e| panic!("invalid embedded database: {}", e)))
+        .query(addr)
+}
+
+
+#[cfg(test)]
+fn sample_db() -> Vec<u8> {
+    let header_len = format::HEADER_LEN + format::SECTION_ENTRY_LEN * 2;
+    let metadata = Metadata {
+        version: format::FORMAT_VERSION,
+        tier: format::Tier::DB1,
+        flags: 0,
+        build_timestamp: 1566864000,
+        dataset: "TEST".to_string(),
+        v4_record_count: 1,
+        v4_record_size: V4_RECORD_SIZE as u16,
+        v6_record_count: 0,
+        v6_record_size: V6_RECORD_SIZE as u16,
+        sections: vec![
+            format::Section { kind: format::SECTION_V4_RECORDS, offset: header_len as u32, len: V4_RECORD_SIZE as u32 },
+            format::Section { kind: format::SECTION_V6_RECORDS, offset: (header_len + V4_RECORD_SIZE) as u32, len: 0 },
+        ],
+    };
+    let mut data = Vec::new();
+    metadata.write(&mut data).unwrap();
+    data.extend_from_slice(&0x01000000u32.to_le_bytes());
+    data.extend_from_slice(&0x010000ffu32.to_le_bytes());
+    data.extend_from_slice(&Location::new(12, u16::MAX, u32::MAX).0.to_le_bytes());
+    data
+}
+
+#[test]
+fn test_from_bytes() {
+    let data = sample_db();
+    let db = IpDb::from_slice(&data).unwrap();
+    assert_eq!(db.v4_records().len(), 1);
+    assert!(db.v6_records().is_empty());
+    let location = db.query(&"1.0.0.1".parse().unwrap()).unwrap();
+    assert_eq!(location.country().code(), "AU");
+    assert!(db.query(&"1.0.1.0".parse().unwrap()).is_none());
+
+    assert_eq!(db.metadata().dataset, "TEST");
+    assert_eq!(db.metadata().tier, format::Tier::DB1);
+
+    assert!(db.validate().is_ok());
+
+    match IpDb::from_bytes(data[..data.len() - 1].to_vec()) {
+        Err(Error::Truncated) => (),
+        _ => panic!("expected Error::Truncated"),
+    }
+    let mut foreign = data.clone();
+    foreign[0] = b'X';
+    match IpDb::from_bytes(foreign) {
+        Err(Error::BadMagic) => (),
+        _ => panic!("expected Error::BadMagic"),
+    }
+    for &flags in [0x04u8, format::FLAG_COMPACT].iter() {
+        let mut flagged = data.clone();
+        flagged[11] = flags;
+        match IpDb::from_bytes(flagged) {
+            Err(Error::InvalidHeader) => (),
+            _ => panic!("expected Error::InvalidHeader"),
+        }
+    }
+    let mut huge_table = data.clone();
+    huge_table[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+    match IpDb::from_bytes(huge_table) {
+        Err(Error::Truncated) | Err(Error::InvalidHeader) => (),
+        _ => panic!("expected Error::Truncated"),
+    }
+    let mut duplicate = data.clone();
+    let offset = format::HEADER_LEN + format::SECTION_ENTRY_LEN;
+    duplicate[offset..offset + 4].copy_from_slice(&format::SECTION_V4_RECORDS.to_le_bytes());
+    match IpDb::from_bytes(duplicate) {
+        Err(Error::DuplicateSection(format::SECTION_V4_RECORDS)) => (),
+        _ => panic!("expected Error::DuplicateSection"),
+    }
+    let mut unknown_country = data.clone();
+    *unknown_country.last_mut().unwrap() = 250;
+    let db = IpDb::from_bytes(unknown_country).unwrap();
+    assert!(db.query(&"1.0.0.1".parse().unwrap()).is_none());
+    assert!(db.lookup(&"1.0.0.1".parse().unwrap()).location.is_none());
+    assert_eq!(db.v4_records().iter().count(), 0);
+    match db.validate() {
+        Err(Error::InvalidCountry { index: 0, .. }) => (),
+        _ => panic!("expected Error::InvalidCountry"),
+    }
+    let mut reversed = data.clone();
+    let offset = data.len() - V4_RECORD_SIZE;
+    reversed[offset..offset + 4].copy_from_slice(&0x02000000u32.to_le_bytes());
+    match IpDb::from_bytes(reversed).unwrap().validate() {
+        Err(Error::InvalidRange { index: 0, .. }) => (),
+        _ => panic!("expected Error::InvalidRange"),
+    }
+}
+
+#[test]
+fn test_lookup() {
+    use crate::builder::{ build_sample, SAMPLE_V4_CSV, SAMPLE_V6_CSV };
+
+    let mut db = IpDb::from_bytes(build_sample(SAMPLE_V4_CSV, SAMPLE_V6_CSV, |_| ())).unwrap();
+    assert_eq!(db.query(&"::ffff:1.0.1.1".parse().unwrap()).unwrap().country().code(), "CN");
+    db.set_normalization(Normalization::none());
+    assert!(db.query(&"::ffff:1.0.1.1".parse().unwrap()).is_none());
+    assert_eq!(db.lookup(&"::ffff:192.168.1.1".parse().unwrap()).special, Some(crate::Special::Transition));
+    db.set_normalization(Normalization::default());
+
+    let result = db.lookup(&"1.0.1.1".parse().unwrap());
+    assert_eq!((result.location.is_some(), result.special), (true, None));
+    assert_eq!(result.embedding, None);
+    assert_eq!(result.zone, Zone::V4);
+    assert_eq!(result.index, db.v4_records().search(0x01000101));
+    let (start, end) = result.range.unwrap();
+    assert_eq!((start.to_string().as_str(), end.to_string().as_str()), ("1.0.1.0", "1.0.3.255"));
+    assert_eq!(result.prefix(), Some(("1.0.1.0".parse().unwrap(), 24)));
+
+    let mapped = db.lookup(&"::ffff:1.0.1.1".parse().unwrap());
+    assert_eq!(mapped.addr, "1.0.1.1".parse::<IpAddr>().unwrap());
+    assert_eq!(mapped.embedding, Some(Embedding::Mapped));
+    assert_eq!((mapped.zone, mapped.index, mapped.range), (result.zone, result.index, result.range));
+
+    let result = db.lookup(&"::ffff:192.168.1.1".parse().unwrap());
+    assert_eq!(result.special, Some(crate::Special::Private));
+    assert!(result.location.is_none());
+    assert!(db.lookup(&"8.8.8.8".parse().unwrap()).is_unknown());
+}
+
+#[test]
+fn test_lookup_batch() {
+    use crate::builder::{ build_sample, SAMPLE_V4_CSV, SAMPLE_V6_CSV };
+
+    let addrs = ["1.0.3.1", "2001:200::1", "0.0.0.1", "1.0.1.1", "::ffff:1.0.0.1", "1.0.4.1", "255.255.255.255", "1.0.1.2"]
+        .iter()
+        .map(|addr| addr.parse::<IpAddr>().unwrap())
+        .collect::<Vec<_>>();
+
+    for &(compact, eytzinger) in [(false, false), (true, false), (false, true), (true, true)].iter() {
+        let buf = build_sample(SAMPLE_V4_CSV, SAMPLE_V6_CSV, |builder| {
+            builder.set_compact(compact);
+            builder.set_eytzinger(eytzinger);
+        });
+        let db = IpDb::from_bytes(buf).unwrap();
+        db.validate().unwrap();
+
+        let results = db.lookup_batch(&addrs);
+        let expected = addrs.iter().map(|addr| db.lookup(addr)).collect::<Vec<_>>();
+        assert_eq!(results, expected);
+        let countries = results.iter()
+            .map(|result| result.location.map_or("-", |location| location.country().code()))
+            .collect::<Vec<_>>();
+        assert_eq!(countries, vec!["CN", "JP", "-", "CN", "US", "AU", "-", "CN"]);
+
+        let mut sorted = addrs.clone();
+        sorted.sort();
+        assert_eq!(db.lookup_batch(sorted.iter().cloned()), sorted.iter().map(|addr| db.lookup(addr)).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn test_ranges() {
+    use crate::builder::build_sample;
+    use crate::country::Country;
+    use crate::location::{ City, Province };
+
+    let csv = "\
+\"16777216\",\"16777471\",\"US\",\"United States of America\",\"Illinois\",\"Springfield\"
+\"16777472\",\"16777727\",\"US\",\"United States of America\",\"Missouri\",\"Springfield\"
+\"16777728\",\"16777983\",\"US\",\"United States of America\",\"Georgia\",\"Atlanta\"
+\"16777984\",\"16778239\",\"IN\",\"India\",\"Punjab\",\"Amritsar\"
+";
+    let db = IpDb::from_bytes(build_sample(csv, "", |_| ())).unwrap();
+    let missouri = Province::find(&db, Country::US, "Missouri").unwrap();
+    let springfields = City::find(&db, Country::US, "Springfield");
+
+    let ranges = db.ranges(Country::US).collect::<Vec<_>>();
+    assert_eq!(ranges.len(), 3);
+    assert_eq!(ranges[0], ("1.0.0.0".parse().unwrap(), "1.0.0.255".parse().unwrap()));
+    let ranges = db.ranges(missouri).collect::<Vec<_>>();
+    assert_eq!(ranges, vec![("1.0.1.0".parse().unwrap(), "1.0.1.255".parse().unwrap())]);
+    assert_eq!(db.v4_records().matching(springfields[0]).count(), 1);
+    assert_eq!(db.v6_records().matching(Country::US).count(), 0);
+}
+
+#[test]
+fn test_details() {
+    use crate::builder::build_sample;
+
+    let csv = "\
+\"16777216\",\"16777471\",\"US\",\"United States of America\",\"California\",\"Los Angeles\",\"34.052230\",\"-118.243680\",\"90001\",\"-07:00\"
+\"16777472\",\"16778239\",\"CN\",\"China\",\"Fujian\",\"Fuzhou\",\"26.061390\",\"119.306110\",\"-\",\"+08:00\"
+";
+    let db = IpDb::from_bytes(build_sample(csv, "", |_| ())).unwrap();
+
+    let details = db.details(&"1.0.1.1".parse().unwrap()).unwrap();
+    let result = db.lookup(&"1.0.1.1".parse().unwrap());
+    assert_eq!(db.details_at(result.zone, result.index.unwrap()), Some(details));
+    assert_eq!(db.details_at(Zone::V4, 2), None);
+
+    let los_angeles = "1.0.0.1".parse().unwrap();
+    let fuzhou = "1.0.1.1".parse().unwrap();
+    let d = db.distance_km(&los_angeles, &fuzhou).unwrap();
+    assert!((d - 10900.0).abs() < 200.0, "{}", d);
+    assert_eq!(db.nearest(&"1.0.0.200".parse().unwrap(), &[fuzhou, los_angeles]), Some((1, 0.0)));
+    assert!(db.distance_km(&los_angeles, &"9.9.9.9".parse().unwrap()).is_none());
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn test_open_mmap() {
+    let path = std::env::temp_dir().join(format!("ip2location-test-{}.bin", std::process::id()));
+    fs::write(&path, sample_db()).unwrap();
+
+    let db = IpDb::open_mmap(&path).unwrap();
+    let location = db.query(&"1.0.0.255".parse().unwrap()).unwrap();
+    assert_eq!(location.country().code(), "AU");
+
+    drop(db);
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,58 @@
+use crate::geo::Coordinates;
+use crate::format::DETAILS_SIZE;
+
+
+/// Per-range fields only present in DB5 and higher tiers.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Details<'a> {
+    pub latitude: Option<f32>,
+    pub longitude: Option<f32>,
+    pub zip_code: Option<&'a str>,
+    pub time_zone: Option<&'a str>,
+}
+
+impl Details<'_> {
+    pub fn coordinates(&self) -> Option<Coordinates> {
+        match (self.latitude, self.longitude) {
+            (Some(latitude), Some(longitude)) => Some(Coordinates::new(latitude as f64, longitude as f64)),
+            _ => None,
+        }
+    }
+}
+
+/// `Details` as stored in the database, with string table indices.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) struct RawDetails {
+    pub latitude: f32,
+    pub longitude: f32,
+    pub zip_code: u32,
+    pub time_zone: u16,
+}
+
+impl Default for RawDetails {
+    fn default() -> Self {
+        Self { latitude: 0.0, longitude: 0.0, zip_code: u32::MAX, time_zone: u16::MAX }
+    }
+}
+
+impl RawDetails {
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < DETAILS_SIZE {
+            return None;
+        }
+        let latitude = f32::from_le_bytes([ bytes[0], bytes[1], bytes[2], bytes[3] ]);
+        let longitude = f32::from_le_bytes([ bytes[4], bytes[5], bytes[6], bytes[7] ]);
+        let zip_code = u32::from_le_bytes([ bytes[8], bytes[9], bytes[10], bytes[11] ]);
+        let time_zone = u16::from_le_bytes([ bytes[12], bytes[13] ]);
+
+        Some(Self { latitude, longitude, zip_code, time_zone })
+    }
+
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.latitude.to_le_bytes());
+        buf.extend_from_slice(&self.longitude.to_le_bytes());
+        buf.extend_from_slice(&self.zip_code.to_le_bytes());
+        buf.extend_from_slice(&self.time_zone.to_le_bytes());
+        buf.extend_from_slice(&[0u8; 2]);
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt;
+use std::net::{ IpAddr, Ipv4Addr, Ipv6Addr };
+
+
+/// How an IPv4 address is carried inside an IPv6 address.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Embedding {
+    /// `::ffff:a.b.c.d`
+    Mapped,
+    /// `2002:aabb:ccdd::/48`
+    SixToFour,
+    /// `2001::/32`, the client address is stored inverted in the last 32 bits.
+    Teredo,
+}
+
+impl fmt::Display for Embedding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Embedding::Mapped => write!(f, "IPv4-mapped"),
+            Embedding::SixToFour => write!(f, "6to4"),
+            Embedding::Teredo => write!(f, "Teredo"),
+        }
+    }
+}
+
+/// Which embeddings `IpDb` unwraps before searching, so the embedded IPv4
+/// address is looked up in the v4 zone. All are enabled by default.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Normalization {
+    pub mapped: bool,
+    pub six_to_four: bool,
+    pub teredo: bool,
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Self { mapped: true, six_to_four: true, teredo: true }
+    }
+}
+
+impl Normalization {
+    /// Searches every IPv6 address in the v6 zone as is.
+    pub fn none() -> Self {
+        Self { mapped: false, six_to_four: false, teredo: false }
+    }
+
+    /// Returns the address to search and the embedding it was taken from.
+    pub fn normalize(&self, addr: IpAddr) -> (IpAddr, Option<Embedding>) {
+        let v6_addr = match addr {
+            IpAddr::V4(_) => return (addr, None),
+            IpAddr::V6(v6_addr) => v6_addr,
+        };
+
+        match embedded_v4(&v6_addr) {
+            Some((v4_addr, embedding)) if self.enabled(embedding) => (IpAddr::V4(v4_addr), Some(embedding)),
+            _ => (addr, None),
+        }
+    }
+
+    fn enabled(&self, embedding: Embedding) -> bool {
+        match embedding {
+            Embedding::Mapped => self.mapped,
+            Embedding::SixToFour => self.six_to_four,
+            Embedding::Teredo => self.teredo,
+        }
+    }
+}
+
+/// The IPv4 address embedded in `addr`, if any.
+pub fn embedded_v4(addr: &Ipv6Addr) -> Option<(Ipv4Addr, Embedding)> {
+    let n = u128::from(*addr);
+    let segments = addr.segments();
+    if n >> 32 == 0xffff {
+        Some((Ipv4Addr::from(n as u32), Embedding::Mapped))
+    } else if segments[0] == 0x2002 {
+        Some((Ipv4Addr::from((n >> 80) as u32), Embedding::SixToFour))
+    } else if segments[0] == 0x2001 && segments[1] == 0 {
+        Some((Ipv4Addr::from(!(n as u32)), Embedding::Teredo))
+    } else {
+        None
+    }
+}
+
+
+#[test]
+fn test_normalize() {
+    let normalize = |s: &str| Normalization::default().normalize(s.parse().unwrap());
+    let v4 = |s: &str| s.parse::<IpAddr>().unwrap();
+
+    assert_eq!(normalize("::ffff:1.2.3.4"), (v4("1.2.3.4"), Some(Embedding::Mapped)));
+    assert_eq!(normalize("2002:102:304::1"), (v4("1.2.3.4"), Some(Embedding::SixToFour)));
+    // RFC 4380: server 65.54.227.120, client 192.0.2.45.
+    assert_eq!(normalize("2001:0:4136:e378:8000:63bf:3fff:fdd2"), (v4("192.0.2.45"), Some(Embedding::Teredo)));
+    assert_eq!(normalize("2001:db8::1"), (v4("2001:db8::1"), None));
+    assert_eq!(normalize("::1"), (v4("::1"), None));
+    assert_eq!(normalize("8.8.8.8"), (v4("8.8.8.8"), None));
+
+    let normalization = Normalization { six_to_four: false, ..Normalization::default() };
+    assert_eq!(normalization.normalize(v4("2002:102:304::1")), (v4("2002:102:304::1"), None));
+    assert_eq!(Normalization::none().normalize(v4("::ffff:1.2.3.4")), (v4("::ffff:1.2.3.4"), None));
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+use std::io;
+
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The buffer is too short to hold the header, or a section extends
+    /// past the end of the buffer.
+    Truncated,
+    /// The file does not start with `format::MAGIC`.
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// A header field holds a value this reader does not understand.
+    InvalidHeader,
+    /// A required section is absent from the section table.
+    MissingSection(u32),
+    /// The section table lists the same section kind more than once.
+    DuplicateSection(u32),
+    /// Two sections share bytes, or a section overlaps the header.
+    OverlappingZones(u32, u32),
+    /// The section length is not a whole number of records, or does not
+    /// match the record count in the header.
+    MisalignedZone(u32),
+    /// The record's `start` is greater than its `end`.
+    InvalidRange { section: u32, index: usize },
+    /// The record does not start after the previous one.
+    UnsortedRecords { section: u32, index: usize },
+    /// The record starts inside the previous record's range.
+    OverlappingRanges { section: u32, index: usize },
+    InvalidCountry { section: u32, index: usize },
+    /// The record's province or city index is out of range.
+    InvalidLocation { section: u32, index: usize },
+    /// A string table entry is not UTF-8 or is out of order.
+    InvalidString { section: u32, index: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Truncated => write!(f, "database is truncated"),
+            Error::BadMagic => write!(f, "not an ip2location database"),
+            Error::UnsupportedVersion(v) => write!(f, "unsupported database format version {}", v),
+            Error::InvalidHeader => write!(f, "database header is invalid"),
+            Error::MissingSection(kind) => write!(f, "database section {} is missing", kind),
+            Error::DuplicateSection(kind) => write!(f, "database section {} is listed more than once", kind),
+            Error::OverlappingZones(a, b) => write!(f, "database sections {} and {} overlap", a, b),
+            Error::MisalignedZone(kind) => write!(f, "database section {} is misaligned", kind),
+            Error::InvalidRange { section, index } => {
+                write!(f, "record {} in section {} ends before it starts", index, section)
+            },
+            Error::UnsortedRecords { section, index } => {
+                write!(f, "record {} in section {} is out of order", index, section)
+            },
+            Error::OverlappingRanges { section, index } => {
+                write!(f, "record {} in section {} overlaps the previous record", index, section)
+            },
+            Error::InvalidCountry { section, index } => {
+                write!(f, "record {} in section {} has an invalid country index", index, section)
+            },
+            Error::InvalidLocation { section, index } => {
+                write!(f, "record {} in section {} has an invalid province or city index", index, section)
+            },
+            Error::InvalidString { section, index } => {
+                write!(f, "string {} in section {} is invalid", index, section)
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
@@ -0,0 +1,127 @@
+use std::io::{self, Write};
+
+use crate::address::Address;
+use crate::cidr::{ Cidr, CidrSet };
+
+
+// NOTE: 每个集合按地址族拆成 `<name>_v4` 和 `<name>_v6` 两个，
+//       nft 的集合即使为空也会创建，方便规则直接引用；ipset 只创建选中的地址族。
+
+/// Writes an `nft -f` script that creates (or refills) one interval set per
+/// family for every `(name, set)` in the `inet` table `table`.
+///
+/// The script only touches the listed sets, rules referring to them stay in
+/// place and the whole file is applied atomically.
+pub fn write_nftables<W: Write + ?Sized>(w: &mut W, table: &str, sets: &[(String, CidrSet)]) -> io::Result<()> {
+    writeln!(w, "add table inet {}", table)?;
+    for (name, set) in sets.iter() {
+        write_nft_set(w, table, &format!("{}_v4", name), "ipv4_addr", set.v4.iter().map(|cidr| cidr.to_string()))?;
+        write_nft_set(w, table, &format!("{}_v6", name), "ipv6_addr", set.v6.iter().map(|cidr| cidr.to_string()))?;
+    }
+
+    Ok(())
+}
+
+fn write_nft_set<W, I>(w: &mut W, table: &str, name: &str, kind: &str, elements: I) -> io::Result<()>
+    where W: Write + ?Sized,
+          I: Iterator<Item = String> {
+    writeln!(w, "add set inet {} {} {{ type {}; flags interval; }}", table, name, kind)?;
+    writeln!(w, "flush set inet {} {}", table, name)?;
+
+    let mut elements = elements.peekable();
+    if elements.peek().is_none() {
+        return Ok(());
+    }
+    writeln!(w, "add element inet {} {} {{", table, name)?;
+    while let Some(element) = elements.next() {
+        let separator = if elements.peek().is_some() { "," } else { "" };
+        writeln!(w, "    {}{}", element, separator)?;
+    }
+    writeln!(w, "}}")
+}
+
+/// Writes an `ipset restore` script with one `hash:net` set per family for
+/// every `(name, set)`. Existing sets are flushed and refilled, the sets of
+/// a family turned off by `v4` or `v6` are left out.
+pub fn write_ipset<W: Write + ?Sized>(w: &mut W, sets: &[(String, CidrSet)], v4: bool, v6: bool) -> io::Result<()> {
+    for (name, set) in sets.iter() {
+        if v4 {
+            write_ipset_set(w, &format!("{}_v4", name), "inet", &set.v4)?;
+        }
+        if v6 {
+            write_ipset_set(w, &format!("{}_v6", name), "inet6", &set.v6)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_ipset_set<W: Write + ?Sized, T: Address>(w: &mut W, name: &str, family: &str, cidrs: &[Cidr<T>]) -> io::Result<()> {
+    let maxelem = (cidrs.len() + 1).max(65536);
+    writeln!(w, "create {} hash:net family {} maxelem {} -exist", name, family, maxelem)?;
+    writeln!(w, "flush {}", name)?;
+    for cidr in cidrs.iter() {
+        if cidr.prefix_len == 0 {
+            // NOTE: hash:net 不接受 /0，拆成两个 /1。
+            let (low, high) = if family == "inet" { ("0.0.0.0/1", "128.0.0.0/1") } else { ("::/1", "8000::/1") };
+            writeln!(w, "add {} {}", name, low)?;
+            writeln!(w, "add {} {}", name, high)?;
+        } else {
+            writeln!(w, "add {} {}", name, cidr)?;
+        }
+    }
+
+    Ok(())
+}
+
+
+#[test]
+fn test_write_firewall() {
+    let mut cn = CidrSet::default();
+    cn.insert("1.0.1.0/24");
+    cn.insert("1.0.2.0/23");
+    let sets = vec![("cn".to_string(), cn)];
+
+    let mut buf = Vec::new();
+    write_nftables(&mut buf, "geoip", &sets).unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), "\
+add table inet geoip
+add set inet geoip cn_v4 { type ipv4_addr; flags interval; }
+flush set inet geoip cn_v4
+add element inet geoip cn_v4 {
+    1.0.1.0/24,
+    1.0.2.0/23
+}
+add set inet geoip cn_v6 { type ipv6_addr; flags interval; }
+flush set inet geoip cn_v6
+");
+
+    let mut all = CidrSet::default();
+    all.insert("::/0");
+    let sets = vec![("cn".to_string(), sets[0].1.clone()), ("all".to_string(), all)];
+    let mut buf = Vec::new();
+    write_ipset(&mut buf, &sets, true, true).unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), "\
+create cn_v4 hash:net family inet maxelem 65536 -exist
+flush cn_v4
+add cn_v4 1.0.1.0/24
+add cn_v4 1.0.2.0/23
+create cn_v6 hash:net family inet6 maxelem 65536 -exist
+flush cn_v6
+create all_v4 hash:net family inet maxelem 65536 -exist
+flush all_v4
+create all_v6 hash:net family inet6 maxelem 65536 -exist
+flush all_v6
+add all_v6 ::/1
+add all_v6 8000::/1
+");
+
+    let mut buf = Vec::new();
+    write_ipset(&mut buf, &sets[..1], true, false).unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), "\
+create cn_v4 hash:net family inet maxelem 65536 -exist
+flush cn_v4
+add cn_v4 1.0.1.0/24
+add cn_v4 1.0.2.0/23
+");
+}
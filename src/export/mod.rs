@@ -0,0 +1,11 @@
+// Writers that turn a `CidrSet` into configuration for other tools.
+mod route;
+mod firewall;
+mod pac;
+mod rules;
+mod zlib;
+
+pub use route::{ IpRoute, write_ip_route, write_wireguard };
+pub use firewall::{ write_nftables, write_ipset };
+pub use pac::write_pac;
+pub use rules::{ ClashBehavior, write_clash, write_srs, write_geoip_dat };
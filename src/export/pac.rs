@@ -0,0 +1,128 @@
+use std::io::{self, Write};
+
+use crate::cidr::{ self, CidrSet };
+
+
+/// Ranges per line in the generated tables.
+const RANGES_PER_LINE: usize = 8;
+
+// NOTE: V4 存放 [start, end, start, end, ...] 整数，V6 存放 32 位十六进制字符串，
+//       定长字符串的字典序与数值顺序一致，两张表共用同一个二分查找。
+//       ::ffff:a.b.c.d 和数据库查询一样按其中的 IPv4 地址在 V4 里查。
+const MATCHER: &str = r#"
+function search(table, key) {
+    var lo = 0, hi = table.length / 2;
+    while (lo < hi) {
+        var mid = (lo + hi) >>> 1;
+        if (table[mid * 2 + 1] < key) {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    return lo < table.length / 2 && table[lo * 2] <= key;
+}
+
+function v4Key(ip) {
+    var p = ip.split(".");
+    return p[0] * 16777216 + p[1] * 65536 + p[2] * 256 + p[3] * 1;
+}
+
+function v6Key(ip) {
+    var halves = ip.split("%")[0].split("::");
+    var head = halves[0] ? halves[0].split(":") : [];
+    var tail = halves.length > 1 && halves[1] ? halves[1].split(":") : [];
+    var groups = tail.length ? tail : head;
+    var last = groups[groups.length - 1];
+    if (last && last.indexOf(".") >= 0) {
+        var n = v4Key(last);
+        groups.splice(groups.length - 1, 1, Math.floor(n / 65536).toString(16), (n % 65536).toString(16));
+    }
+    while (head.length + tail.length < 8) {
+        head.push("0");
+    }
+    groups = head.concat(tail);
+    var key = "";
+    for (var i = 0; i < 8; i++) {
+        key += ("0000" + groups[i]).slice(-4);
+    }
+    return key.toLowerCase();
+}
+
+function isDirect(ip) {
+    if (ip.indexOf(":") >= 0) {
+        var key = v6Key(ip);
+        if (key.slice(0, 24) === "00000000000000000000ffff") {
+            return search(V4, parseInt(key.slice(24), 16));
+        }
+        return search(V6, key);
+    }
+    return search(V4, v4Key(ip));
+}
+
+function FindProxyForURL(url, host) {
+    var ip = host.replace(/^\[|\]$/g, "");
+    if (!/^[0-9.]+$/.test(ip) && ip.indexOf(":") < 0) {
+        if (isPlainHostName(host)) {
+            return "DIRECT";
+        }
+        ip = typeof dnsResolveEx === "function" ? dnsResolveEx(host).split(";")[0] : dnsResolve(host);
+        if (!ip) {
+            return PROXY;
+        }
+    }
+    return isDirect(ip) ? "DIRECT" : PROXY;
+}
+"#;
+
+/// Writes a proxy auto-config script that connects directly to every
+/// address in `direct` and uses `proxy` (e.g. `"SOCKS5 127.0.0.1:1080"`)
+/// for everything else.
+///
+/// Host names are resolved with `dnsResolveEx` where the browser has it,
+/// `dnsResolve` otherwise. Plain host names always go direct.
+pub fn write_pac<W: Write + ?Sized>(w: &mut W, direct: &CidrSet, proxy: &str) -> io::Result<()> {
+    let v4 = cidr::merge(direct.v4.iter().map(|cidr| (cidr.first(), cidr.last())));
+    let v6 = cidr::merge(direct.v6.iter().map(|cidr| (cidr.first(), cidr.last())));
+
+    writeln!(w, "var PROXY = \"{}\";", proxy.replace('\\', "\\\\").replace('"', "\\\""))?;
+    write_table(w, "V4", v4.iter().map(|(start, end)| format!("{},{}", start, end)))?;
+    write_table(w, "V6", v6.iter().map(|(start, end)| format!("\"{:032x}\",\"{:032x}\"", start, end)))?;
+    w.write_all(MATCHER.as_bytes())
+}
+
+fn write_table<W, I>(w: &mut W, name: &str, ranges: I) -> io::Result<()>
+    where W: Write + ?Sized,
+          I: Iterator<Item = String> {
+    let ranges = ranges.collect::<Vec<_>>();
+    writeln!(w, "var {} = [", name)?;
+    for (idx, line) in ranges.chunks(RANGES_PER_LINE).enumerate() {
+        let separator = if (idx + 1) * RANGES_PER_LINE < ranges.len() { "," } else { "" };
+        writeln!(w, "    {}{}", line.join(","), separator)?;
+    }
+    writeln!(w, "];")
+}
+
+
+#[test]
+fn test_write_pac() {
+    let mut direct = CidrSet::default();
+    direct.insert("1.0.1.0/24");
+    direct.insert("1.0.2.0/23");
+    direct.insert("2001:db8::/32");
+
+    let mut buf = Vec::new();
+    write_pac(&mut buf, &direct, "SOCKS5 127.0.0.1:1080").unwrap();
+    let pac = String::from_utf8(buf).unwrap();
+    assert!(pac.starts_with("\
+var PROXY = \"SOCKS5 127.0.0.1:1080\";
+var V4 = [
+    16777472,16778239
+];
+var V6 = [
+    \"20010db8000000000000000000000000\",\"20010db8ffffffffffffffffffffffff\"
+];
+"));
+    assert!(pac.contains("function FindProxyForURL(url, host)"));
+    assert!(pac.contains("if (key.slice(0, 24) === \"00000000000000000000ffff\") {"));
+}
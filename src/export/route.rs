@@ -0,0 +1,70 @@
+use std::io::{self, Write};
+
+use crate::cidr::CidrSet;
+
+
+/// Where `write_ip_route` sends each prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpRoute {
+    pub dev: String,
+    pub table: Option<u32>,
+    pub metric: Option<u32>,
+}
+
+impl IpRoute {
+    pub fn new(dev: &str) -> Self {
+        Self { dev: dev.to_string(), table: None, metric: None }
+    }
+}
+
+/// Writes an `ip -batch` file with one `route replace` per prefix, so the
+/// file can be applied again after the set changes.
+pub fn write_ip_route<W: Write + ?Sized>(w: &mut W, set: &CidrSet, route: &IpRoute) -> io::Result<()> {
+    let mut options = format!("dev {}", route.dev);
+    if let Some(table) = route.table {
+        options.push_str(&format!(" table {}", table));
+    }
+    if let Some(metric) = route.metric {
+        options.push_str(&format!(" metric {}", metric));
+    }
+
+    for cidr in set.v4.iter() {
+        writeln!(w, "route replace {} {}", cidr, options)?;
+    }
+    for cidr in set.v6.iter() {
+        writeln!(w, "route replace {} {}", cidr, options)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the `AllowedIPs = ...` line of a WireGuard `[Peer]` section.
+pub fn write_wireguard<W: Write + ?Sized>(w: &mut W, set: &CidrSet) -> io::Result<()> {
+    let cidrs = set.v4.iter().map(|cidr| cidr.to_string())
+        .chain(set.v6.iter().map(|cidr| cidr.to_string()))
+        .collect::<Vec<_>>();
+
+    writeln!(w, "AllowedIPs = {}", cidrs.join(", "))
+}
+
+
+#[test]
+fn test_write_routes() {
+    let mut local = CidrSet::default();
+    local.insert("128.0.0.0/1");
+    local.insert("::/1");
+    let tunnel = local.complement();
+
+    let mut route = IpRoute::new("wg0");
+    route.table = Some(100);
+    let mut buf = Vec::new();
+    write_ip_route(&mut buf, &tunnel, &route).unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), "\
+route replace 0.0.0.0/1 dev wg0 table 100
+route replace 8000::/1 dev wg0 table 100
+");
+
+    let mut buf = Vec::new();
+    write_wireguard(&mut buf, &tunnel).unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), "AllowedIPs = 0.0.0.0/1, 8000::/1\n");
+}
@@ -0,0 +1,179 @@
+use std::io::{self, Write};
+
+use crate::address::Address;
+use crate::cidr::{ self, Cidr, CidrSet };
+use super::zlib;
+
+
+/// Line format of `write_clash`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ClashBehavior {
+    /// `behavior: ipcidr`, one prefix per line.
+    IpCidr,
+    /// `behavior: classical`, `IP-CIDR,<prefix>,no-resolve` per line.
+    Classical,
+}
+
+/// Writes a Clash rule provider in the `text` format.
+pub fn write_clash<W: Write + ?Sized>(w: &mut W, set: &CidrSet, behavior: ClashBehavior) -> io::Result<()> {
+    let lines = set.v4.iter().map(|cidr| ("IP-CIDR", cidr.to_string()))
+        .chain(set.v6.iter().map(|cidr| ("IP-CIDR6", cidr.to_string())));
+    for (kind, cidr) in lines {
+        match behavior {
+            ClashBehavior::IpCidr => writeln!(w, "{}", cidr)?,
+            ClashBehavior::Classical => writeln!(w, "{},{},no-resolve", kind, cidr)?,
+        }
+    }
+
+    Ok(())
+}
+
+// sing-box binary rule set (.srs)
+//
+// magic    [u8; 3]  "SRS"
+// version  u8       1
+// zlib stream of:
+//   rule_count  uvarint
+//   rule:
+//     type     u8  0 (default rule)
+//     item     u8  6 (ip_cidr)
+//     ip_set:
+//       version      u8       1
+//       range_count  u64 BE
+//       ranges       { from, to: uvarint len + address bytes }
+//     item     u8  0xFF (end of items)
+//     invert   u8  0
+const SRS_MAGIC: [u8; 3] = *b"SRS";
+const SRS_VERSION: u8 = 1;
+const SRS_RULE_DEFAULT: u8 = 0;
+const SRS_ITEM_IP_CIDR: u8 = 6;
+const SRS_ITEM_FINAL: u8 = 0xFF;
+const SRS_IP_SET_VERSION: u8 = 1;
+
+/// Writes a sing-box binary rule set with one `ip_cidr` rule matching `set`.
+///
+/// The zlib stream is not compressed, sing-box reads it all the same.
+pub fn write_srs<W: Write + ?Sized>(w: &mut W, set: &CidrSet) -> io::Result<()> {
+    let v4 = merged(&set.v4);
+    let v6 = merged(&set.v6);
+
+    let mut rules = Vec::new();
+    write_uvarint(&mut rules, 1);
+    rules.push(SRS_RULE_DEFAULT);
+    rules.push(SRS_ITEM_IP_CIDR);
+    rules.push(SRS_IP_SET_VERSION);
+    rules.extend_from_slice(&((v4.len() + v6.len()) as u64).to_be_bytes());
+    for (start, end) in v4.iter() {
+        write_srs_addr(&mut rules, &start.to_be_bytes());
+        write_srs_addr(&mut rules, &end.to_be_bytes());
+    }
+    for (start, end) in v6.iter() {
+        write_srs_addr(&mut rules, &start.to_be_bytes());
+        write_srs_addr(&mut rules, &end.to_be_bytes());
+    }
+    rules.push(SRS_ITEM_FINAL);
+    rules.push(0);
+
+    w.write_all(&SRS_MAGIC)?;
+    w.write_all(&[SRS_VERSION])?;
+    zlib::write_stored(w, &rules)
+}
+
+fn merged<T: Address>(cidrs: &[Cidr<T>]) -> Vec<(T, T)> {
+    cidr::merge(cidrs.iter().map(|cidr| (cidr.first(), cidr.last())))
+}
+
+fn write_srs_addr(buf: &mut Vec<u8>, addr: &[u8]) {
+    write_uvarint(buf, addr.len() as u64);
+    buf.extend_from_slice(addr);
+}
+
+// v2ray geoip.dat (protobuf)
+//
+// message CIDR      { bytes ip = 1; uint32 prefix = 2; }
+// message GeoIP     { string country_code = 1; repeated CIDR cidr = 2; }
+// message GeoIPList { repeated GeoIP entry = 1; }
+
+/// Writes a v2ray / Xray `geoip.dat` with one entry per `(code, set)`,
+/// e.g. `("CN", set)` for `geoip:cn`.
+pub fn write_geoip_dat<W: Write + ?Sized>(w: &mut W, entries: &[(String, CidrSet)]) -> io::Result<()> {
+    let mut list = Vec::new();
+    for (code, set) in entries.iter() {
+        let mut entry = Vec::new();
+        write_bytes_field(&mut entry, 1, code.to_uppercase().as_bytes());
+        for cidr in set.v4.iter() {
+            write_bytes_field(&mut entry, 2, &geoip_cidr(&cidr.network.to_be_bytes(), cidr.prefix_len));
+        }
+        for cidr in set.v6.iter() {
+            write_bytes_field(&mut entry, 2, &geoip_cidr(&cidr.network.to_be_bytes(), cidr.prefix_len));
+        }
+        write_bytes_field(&mut list, 1, &entry);
+    }
+
+    w.write_all(&list)
+}
+
+fn geoip_cidr(ip: &[u8], prefix_len: u8) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(ip.len() + 4);
+    write_bytes_field(&mut buf, 1, ip);
+    // NOTE: proto3 不写默认值 0。
+    if prefix_len > 0 {
+        write_uvarint(&mut buf, 2 << 3);
+        write_uvarint(&mut buf, prefix_len as u64);
+    }
+    buf
+}
+
+/// A length-delimited (wire type 2) protobuf field.
+fn write_bytes_field(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_uvarint(buf, field << 3 | 2);
+    write_uvarint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_uvarint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+
+#[test]
+fn test_write_rules() {
+    let mut set = CidrSet::default();
+    set.insert("1.0.1.0/24");
+    set.insert("1.0.2.0/23");
+    set.insert("2001:db8::/32");
+
+    let mut buf = Vec::new();
+    write_clash(&mut buf, &set, ClashBehavior::Classical).unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), "\
+IP-CIDR,1.0.1.0/24,no-resolve
+IP-CIDR,1.0.2.0/23,no-resolve
+IP-CIDR6,2001:db8::/32,no-resolve
+");
+
+    let mut buf = Vec::new();
+    write_srs(&mut buf, &set).unwrap();
+    assert_eq!(&buf[..4], b"SRS\x01");
+    let rules = &buf[4 + 2 + 5..buf.len() - 4];
+    assert_eq!(&rules[..4], &[1, SRS_RULE_DEFAULT, SRS_ITEM_IP_CIDR, SRS_IP_SET_VERSION]);
+    assert_eq!(&rules[4..12], &2u64.to_be_bytes());
+    assert_eq!(&rules[12..22], &[4, 1, 0, 1, 0, 4, 1, 0, 3, 255]);
+    assert_eq!(rules.len(), 12 + 10 + 34 + 2);
+    assert_eq!(&rules[rules.len() - 2..], &[SRS_ITEM_FINAL, 0]);
+
+    let mut cn = CidrSet::default();
+    cn.insert("1.0.1.0/24");
+    cn.insert("::/0");
+    let mut buf = Vec::new();
+    write_geoip_dat(&mut buf, &[("cn".to_string(), cn)]).unwrap();
+    assert_eq!(buf, [
+        0x0a, 34,
+            0x0a, 2, b'C', b'N',
+            0x12, 8, 0x0a, 4, 1, 0, 1, 0, 0x10, 24,
+            0x12, 18, 0x0a, 16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ]);
+}
@@ -0,0 +1,60 @@
+use std::io::{self, Write};
+
+
+// NOTE: 只写 stored (不压缩) 的 deflate 块，任何 zlib 解码器都能读，省去一个依赖。
+const MAX_BLOCK_LEN: usize = u16::MAX as usize;
+
+/// Wraps `data` in a zlib stream (RFC 1950) made of stored deflate blocks.
+pub fn write_stored<W: Write + ?Sized>(w: &mut W, data: &[u8]) -> io::Result<()> {
+    // CMF: deflate, 32K window; FLG: fastest, no dictionary, check bits.
+    w.write_all(&[0x78, 0x01])?;
+
+    let mut blocks = data.chunks(MAX_BLOCK_LEN).peekable();
+    if blocks.peek().is_none() {
+        w.write_all(&[0x01, 0x00, 0x00, 0xff, 0xff])?;
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        w.write_all(&[last as u8])?;
+        w.write_all(&len.to_le_bytes())?;
+        w.write_all(&(!len).to_le_bytes())?;
+        w.write_all(block)?;
+    }
+
+    w.write_all(&adler32(data).to_be_bytes())
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let mut a = 1u32;
+    let mut b = 0u32;
+    // NOTE: 5552 字节内不会溢出 u32。
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+
+    (b << 16) | a
+}
+
+
+#[test]
+fn test_zlib() {
+    assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    assert_eq!(adler32(b""), 1);
+
+    let mut buf = Vec::new();
+    write_stored(&mut buf, b"abc").unwrap();
+    assert_eq!(buf, [0x78, 0x01, 0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c', 0x02, 0x4d, 0x01, 0x27]);
+
+    let mut buf = Vec::new();
+    write_stored(&mut buf, &vec![0u8; MAX_BLOCK_LEN + 1]).unwrap();
+    assert_eq!(buf.len(), 2 + 5 + MAX_BLOCK_LEN + 5 + 1 + 4);
+    assert_eq!(buf[2], 0x00);
+    assert_eq!(buf[2 + 5 + MAX_BLOCK_LEN], 0x01);
+}
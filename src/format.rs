@@ -0,0 +1,341 @@
+use std::io::{self, Write};
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+
+use crate::error::Error;
+
+
+// Header (64 bytes, Little Endian)
+//
+//  0  magic            [u8; 8]  "IP2LOCDB"
+//  8  version          u16
+// 10  tier             u8
+// 11  flags            u8
+// 12  section_count    u32
+// 16  build_timestamp  u64      unix seconds
+// 24  v4_record_count  u32
+// 28  v4_record_size   u16
+// 30  v6_record_size   u16
+// 32  v6_record_count  u32
+// 36  reserved         [u8; 4]
+// 40  dataset          [u8; 24] UTF-8, NUL padded
+//
+// Section table: section_count * { kind: u32, offset: u32, len: u32 }
+pub const MAGIC: [u8; 8] = *b"IP2LOCDB";
+pub const FORMAT_VERSION: u16 = 2;
+pub const HEADER_LEN: usize = 64;
+pub const SECTION_ENTRY_LEN: usize = 4 + 4 + 4;
+pub const DATASET_LEN: usize = 24;
+
+/// The builder dropped provinces, cities and details, records only carry
+/// the country. `tier` is still the tier of the source data.
+pub const FLAG_COUNTRY_ONLY: u8 = 0x01;
+/// Always set together with `FLAG_COUNTRY_ONLY`. The records are stored as
+/// `SECTION_V4_STARTS`/`SECTION_V4_COUNTRIES` (and the v6 pair) instead of
+/// `SECTION_V4_RECORDS`/`SECTION_V6_RECORDS`, the record sizes in the header
+/// are `address + 1`.
+pub const FLAG_COMPACT: u8 = 0x02;
+
+pub const SECTION_V4_RECORDS: u32 = 1;
+pub const SECTION_V6_RECORDS: u32 = 2;
+/// Optional, see `strings::StringTable`. Sorted by (country, name), see
+/// `SECTION_PROVINCE_PARENTS`.
+pub const SECTION_PROVINCES: u32 = 3;
+/// Optional, see `strings::StringTable`. Sorted by (country, province, name),
+/// see `SECTION_CITY_PARENTS`.
+pub const SECTION_CITIES: u32 = 4;
+/// Optional, one `DETAILS_SIZE` entry per record in `SECTION_V4_RECORDS`.
+pub const SECTION_V4_DETAILS: u32 = 5;
+/// Optional, one `DETAILS_SIZE` entry per record in `SECTION_V6_RECORDS`.
+pub const SECTION_V6_DETAILS: u32 = 6;
+/// Optional, see `strings::StringTable`.
+pub const SECTION_ZIP_CODES: u32 = 7;
+/// Optional, see `strings::StringTable`.
+pub const SECTION_TIME_ZONES: u32 = 8;
+/// Required with `SECTION_PROVINCES`, one `PROVINCE_PARENT_SIZE` entry per province.
+pub const SECTION_PROVINCE_PARENTS: u32 = 9;
+/// Required with `SECTION_CITIES`, one `CITY_PARENT_SIZE` entry per city.
+pub const SECTION_CITY_PARENTS: u32 = 10;
+/// Compact only, range starts (u32, Little Endian), ascending, the first is 0.
+/// Each range ends where the next one starts.
+pub const SECTION_V4_STARTS: u32 = 11;
+/// Compact only, one country index per start, `COMPACT_GAP` if unassigned.
+pub const SECTION_V4_COUNTRIES: u32 = 12;
+/// Compact only, like `SECTION_V4_STARTS` with u128 starts.
+pub const SECTION_V6_STARTS: u32 = 13;
+/// Compact only, like `SECTION_V4_COUNTRIES`.
+pub const SECTION_V6_COUNTRIES: u32 = 14;
+/// Optional, the v4 range starts (u32, Little Endian) in Eytzinger order:
+/// the key at position `k` (1-based) has its children at `2k` and `2k + 1`,
+/// so the top levels of the search share a few cache lines. One entry per
+/// record (or compact start), requires `SECTION_V4_EYTZINGER_INDEX`.
+pub const SECTION_V4_EYTZINGER: u32 = 15;
+/// Required with `SECTION_V4_EYTZINGER`, the record index (u32, Little
+/// Endian) of the start at the same position.
+pub const SECTION_V4_EYTZINGER_INDEX: u32 = 16;
+/// Optional, like `SECTION_V4_EYTZINGER` with u128 starts.
+pub const SECTION_V6_EYTZINGER: u32 = 17;
+/// Required with `SECTION_V6_EYTZINGER`, like `SECTION_V4_EYTZINGER_INDEX`.
+pub const SECTION_V6_EYTZINGER_INDEX: u32 = 18;
+/// Optional, `V4_PREFIX_INDEX_LEN` record indexes (u32, Little Endian). Entry
+/// `p` is the first record ending at or after `p << 16`, the last entry is the
+/// record count, so an address with top 16 bits `p` lies in records
+/// `index[p]..=index[p + 1]`.
+pub const SECTION_V4_PREFIX_INDEX: u32 = 19;
+/// Optional, `V6_PREFIX_ENTRY_SIZE` entries sorted by prefix, the first one
+/// for prefix 0. Each maps a /32 prefix to the first record ending at or after
+/// `prefix << 96`, lookups use the last entry not above the address' prefix.
+pub const SECTION_V6_PREFIX_INDEX: u32 = 20;
+
+/// Country index of the unassigned ranges in compact databases.
+pub const COMPACT_GAP: u8 = 0xFF;
+
+// Details (16 bytes, Little Endian)
+//
+//  0  latitude   f32
+//  4  longitude  f32
+//  8  zip_code   u32  index into SECTION_ZIP_CODES, u32::MAX if unknown
+// 12  time_zone  u16  index into SECTION_TIME_ZONES, u16::MAX if unknown
+// 14  reserved   u16
+pub const DETAILS_SIZE: usize = 16;
+
+// Province parent (1 byte)
+//
+//  0  country   u8   country index
+pub const PROVINCE_PARENT_SIZE: usize = 1;
+
+// City parent (4 bytes, Little Endian)
+//
+//  0  province  u16  index into SECTION_PROVINCES, u16::MAX if unknown
+//  2  country   u8   country index
+//  3  reserved  u8
+pub const CITY_PARENT_SIZE: usize = 4;
+
+/// Size of an entry in `SECTION_V4_EYTZINGER_INDEX` / `SECTION_V6_EYTZINGER_INDEX`.
+pub const EYTZINGER_INDEX_SIZE: usize = 4;
+
+/// Number of entries in `SECTION_V4_PREFIX_INDEX`, one per /16 and the end.
+pub const V4_PREFIX_INDEX_LEN: usize = (1 << 16) + 1;
+
+// V6 prefix index entry (8 bytes, Little Endian)
+//
+//  0  prefix  u32  top 32 bits of the address
+//  4  first   u32  index of the first record ending at or after `prefix << 96`
+pub const V6_PREFIX_ENTRY_SIZE: usize = 8;
+
+
+/// IP2Location LITE dataset tier the database was built from.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum Tier {
+    /// Country.
+    DB1,
+    /// Country, region, city.
+    DB3,
+    /// DB3 plus latitude, longitude.
+    DB5,
+    /// DB5 plus ZIP code.
+    DB9,
+    /// DB9 plus time zone.
+    DB11,
+}
+
+impl Tier {
+    pub fn from_u8(n: u8) -> Option<Self> {
+        match n {
+            1 => Some(Tier::DB1),
+            3 => Some(Tier::DB3),
+            5 => Some(Tier::DB5),
+            9 => Some(Tier::DB9),
+            11 => Some(Tier::DB11),
+            _ => None,
+        }
+    }
+
+    /// Detects the tier from the number of CSV columns.
+    pub fn from_columns(n: usize) -> Option<Self> {
+        match n {
+            4 => Some(Tier::DB1),
+            6 => Some(Tier::DB3),
+            8 => Some(Tier::DB5),
+            9 => Some(Tier::DB9),
+            10 => Some(Tier::DB11),
+            _ => None,
+        }
+    }
+
+    pub fn columns(&self) -> usize {
+        match self {
+            Tier::DB1 => 4,
+            Tier::DB3 => 6,
+            Tier::DB5 => 8,
+            Tier::DB9 => 9,
+            Tier::DB11 => 10,
+        }
+    }
+
+    pub fn has_city(&self) -> bool {
+        *self >= Tier::DB3
+    }
+
+    pub fn has_coordinates(&self) -> bool {
+        *self >= Tier::DB5
+    }
+
+    pub fn has_zip_code(&self) -> bool {
+        *self >= Tier::DB9
+    }
+
+    pub fn has_time_zone(&self) -> bool {
+        *self >= Tier::DB11
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            Tier::DB1 => 1,
+            Tier::DB3 => 3,
+            Tier::DB5 => 5,
+            Tier::DB9 => 9,
+            Tier::DB11 => 11,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Section {
+    pub kind: u32,
+    pub offset: u32,
+    pub len: u32,
+}
+
+/// Everything stored in the database header.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Metadata {
+    pub version: u16,
+    pub tier: Tier,
+    pub flags: u8,
+    pub build_timestamp: u64,
+    pub dataset: String,
+    pub v4_record_count: u32,
+    pub v4_record_size: u16,
+    pub v6_record_count: u32,
+    pub v6_record_size: u16,
+    pub sections: Vec<Section>,
+}
+
+impl Metadata {
+    pub fn build_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.build_timestamp)
+    }
+
+    pub fn is_country_only(&self) -> bool {
+        self.flags & FLAG_COUNTRY_ONLY != 0
+    }
+
+    pub fn is_compact(&self) -> bool {
+        self.flags & FLAG_COMPACT != 0
+    }
+
+    pub fn section(&self, kind: u32) -> Option<&Section> {
+        self.sections.iter().find(|section| section.kind == kind)
+    }
+
+    /// Size of the header including the section table.
+    pub fn header_len(&self) -> usize {
+        HEADER_LEN + self.sections.len() * SECTION_ENTRY_LEN
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < HEADER_LEN {
+            return Err(Error::Truncated);
+        }
+        if data[0..8] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+
+        let read_u16 = |offset: usize| u16::from_le_bytes([ data[offset], data[offset + 1] ]);
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([ data[offset], data[offset + 1], data[offset + 2], data[offset + 3] ])
+        };
+
+        let version = read_u16(8);
+        if version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let tier = Tier::from_u8(data[10]).ok_or(Error::InvalidHeader)?;
+        let flags = data[11];
+        let section_count = read_u32(12) as usize;
+        // NOTE: 先确认整张 section 表都在缓冲区内，再按文件里的数量做任何处理。
+        let table_end = section_count.checked_mul(SECTION_ENTRY_LEN)
+            .and_then(|n| n.checked_add(HEADER_LEN))
+            .ok_or(Error::InvalidHeader)?;
+        if data.len() < table_end {
+            return Err(Error::Truncated);
+        }
+        let mut timestamp = [0u8; 8];
+        timestamp.copy_from_slice(&data[16..24]);
+        let build_timestamp = u64::from_le_bytes(timestamp);
+        let v4_record_count = read_u32(24);
+        let v4_record_size = read_u16(28);
+        let v6_record_size = read_u16(30);
+        let v6_record_count = read_u32(32);
+
+        let dataset = &data[40..40 + DATASET_LEN];
+        let dataset_len = dataset.iter().position(|&b| b == 0).unwrap_or(DATASET_LEN);
+        let dataset = std::str::from_utf8(&dataset[..dataset_len])
+            .map_err(|_| Error::InvalidHeader)?
+            .to_string();
+
+        let sections = (0..section_count)
+            .map(|idx| {
+                let offset = HEADER_LEN + idx * SECTION_ENTRY_LEN;
+                Section {
+                    kind: read_u32(offset),
+                    offset: read_u32(offset + 4),
+                    len: read_u32(offset + 8),
+                }
+            })
+            .collect::<Vec<_>>();
+        // NOTE: 重复的 section 会让查找和校验看到不同的数据，直接拒绝。
+        //       section 数量来自文件，排序后比较相邻项，避免构造的文件让这里变成平方级。
+        let mut kinds = sections.iter().map(|section| section.kind).collect::<Vec<_>>();
+        kinds.sort_unstable();
+        if let Some(pair) = kinds.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(Error::DuplicateSection(pair[0]));
+        }
+
+        Ok(Metadata {
+            version, tier, flags, build_timestamp, dataset,
+            v4_record_count, v4_record_size, v6_record_count, v6_record_size,
+            sections,
+        })
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut dataset = [0u8; DATASET_LEN];
+        // NOTE: 截断时保持 UTF-8 字符边界。
+        let mut dataset_len = self.dataset.len().min(DATASET_LEN);
+        while !self.dataset.is_char_boundary(dataset_len) {
+            dataset_len -= 1;
+        }
+        dataset[..dataset_len].copy_from_slice(&self.dataset.as_bytes()[..dataset_len]);
+
+        w.write_all(&MAGIC)?;
+        w.write_all(&self.version.to_le_bytes())?;
+        w.write_all(&[ self.tier.as_u8(), self.flags ])?;
+        w.write_all(&(self.sections.len() as u32).to_le_bytes())?;
+        w.write_all(&self.build_timestamp.to_le_bytes())?;
+        w.write_all(&self.v4_record_count.to_le_bytes())?;
+        w.write_all(&self.v4_record_size.to_le_bytes())?;
+        w.write_all(&self.v6_record_size.to_le_bytes())?;
+        w.write_all(&self.v6_record_count.to_le_bytes())?;
+        w.write_all(&[0u8; 4])?;
+        w.write_all(&dataset)?;
+
+        for section in self.sections.iter() {
+            w.write_all(&section.kind.to_le_bytes())?;
+            w.write_all(&section.offset.to_le_bytes())?;
+            w.write_all(&section.len.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use std::fmt;
+
+
+/// Mean Earth radius (IUGG).
+pub const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Coordinates {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Self { latitude, longitude }
+    }
+
+    /// Great-circle distance using the haversine formula.
+    pub fn distance_km(&self, other: &Coordinates) -> f64 {
+        distance_km(*self, *other)
+    }
+}
+
+impl fmt::Display for Coordinates {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {}", self.latitude, self.longitude)
+    }
+}
+
+pub fn distance_km(a: Coordinates, b: Coordinates) -> f64 {
+    let lat1 = a.latitude.to_radians();
+    let lat2 = b.latitude.to_radians();
+    let dlat = (b.latitude - a.latitude).to_radians();
+    let dlon = (b.longitude - a.longitude).to_radians();
+
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_KM * h.sqrt().min(1.0).asin()
+}
+
+
+#[test]
+fn test_distance_km() {
+    let beijing = Coordinates::new(39.9042, 116.4074);
+    let shanghai = Coordinates::new(31.2304, 121.4737);
+    let d = beijing.distance_km(&shanghai);
+    assert!((d - 1067.0).abs() < 5.0, "{}", d);
+    assert_eq!(distance_km(beijing, beijing), 0.0);
+}
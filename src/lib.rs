@@ -0,0 +1,31 @@
+mod address;
+mod country;
+mod location;
+pub mod strings;
+pub mod csv;
+mod details;
+mod geo;
+pub mod cidr;
+mod embedding;
+mod special;
+pub mod export;
+mod error;
+pub mod format;
+mod db;
+mod builder;
+
+pub use address::Address;
+pub use country::{ Country, COUNTRY_CODES, COUNTRY_CODES_LEN };
+pub use location::{ Location, LocationDisplay, Province, City, Place };
+pub use strings::StringTable;
+pub use details::Details;
+pub use geo::{ Coordinates, distance_km, EARTH_RADIUS_KM };
+pub use cidr::{ Cidr, CidrSet };
+pub use embedding::{ Embedding, Normalization, embedded_v4 };
+pub use special::Special;
+pub use error::Error;
+pub use format::{ Metadata, Section, Tier };
+pub use db::{ IpDb, LookupResult, Zone, Record, Records, V4Records, V6Records, V4_RECORD_SIZE, V6_RECORD_SIZE };
+pub use builder::{ Builder, DropReason, Report, MAX_REPORTED_ERRORS };
+#[cfg(feature = "embedded")]
+pub use db::{ IP_DB, query };
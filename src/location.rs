@@ -1,8 +1,8 @@
 use std::fmt;
-use std::str::FromStr;
+use std::ops::Range;
 
-use crate::country::Country;
-use crate::location_db::{ PROVINCES_DB, CITIES_DB };
+use crate::db::IpDb;
+use crate::country::{ Country, COUNTRY_CODES_LEN };
 
 // CC:        242  u8 
 // PAD:            u8
@@ -18,6 +18,14 @@ pub struct Province(pub(crate) u16);
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct City(pub(crate) u32);
 
+/// A country, province or city to select records by, see `IpDb::ranges`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Place {
+    Country(Country),
+    Province(Province),
+    City(City),
+}
+
 
 impl Location {
     pub fn new(country_index: u8, province_index: u16, city_index: u32) -> Self {
@@ -28,12 +36,16 @@ impl Location {
     }
 
     pub fn country(&self) -> Country {
-        Country::from_index((self.0 >> 56) as u8)
+        Country::from_index(self.country_index())
+    }
+
+    pub(crate) fn country_index(&self) -> u8 {
+        (self.0 >> 56) as u8
     }
 
     pub fn province(&self) -> Option<Province> {
         let id = ((self.0 & 0b00000000_00000000_11111111_11111111_00000000_00000000_00000000_00000000) >> 32) as u16;
-        if id == std::u16::MAX {
+        if id == u16::MAX {
             None
         } else {
             Some(Province(id))
@@ -42,7 +54,7 @@ impl Location {
 
     pub fn city(&self) -> Option<City> {
         let id = (self.0 & 0b00000000_00000000_00000000_00000000_11111111_11111111_11111111_11111111) as u32;
-        if id == std::u32::MAX {
+        if id == u32::MAX {
             None
         } else {
             Some(City(id))
@@ -50,46 +62,104 @@ impl Location {
     }
 }
 
+impl Location {
+    /// Resolves province and city names through `db`, e.g. `California,Mountain View "US"`.
+    pub fn display<'a>(&self, db: &'a IpDb) -> LocationDisplay<'a> {
+        LocationDisplay { location: *self, db }
+    }
+}
+
 impl fmt::Debug for Location {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-       write!(f, "{},{} {:?}",
-            self.province().map(|province| format!("{:?}", province) ).unwrap_or("Unknow".to_string()),
-            self.city().map(|city| format!("{:?}", city) ).unwrap_or("Unknow".to_string()),
-            self.country(),
+        f.debug_struct("Location")
+            .field("country", &self.country())
+            .field("province", &self.province())
+            .field("city", &self.city())
+            .finish()
+    }
+}
+
+pub struct LocationDisplay<'a> {
+    location: Location,
+    db: &'a IpDb,
+}
+
+impl fmt::Display for LocationDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let province = self.location.province().and_then(|province| province.name(self.db));
+        let city = self.location.city().and_then(|city| city.name(self.db));
+        write!(f, "{},{} {:?}",
+            province.unwrap_or("Unknow"),
+            city.unwrap_or("Unknow"),
+            self.location.country(),
         )
     }
 }
 
 
+impl Place {
+    pub fn contains(&self, location: &Location) -> bool {
+        match self {
+            Place::Country(country) => location.country_index() == country.index(),
+            Place::Province(province) => location.province() == Some(*province),
+            Place::City(city) => location.city() == Some(*city),
+        }
+    }
+}
+
+impl From<Country> for Place {
+    fn from(country: Country) -> Self {
+        Place::Country(country)
+    }
+}
+
+impl From<Province> for Place {
+    fn from(province: Province) -> Self {
+        Place::Province(province)
+    }
+}
+
+impl From<City> for Place {
+    fn from(city: City) -> Self {
+        Place::City(city)
+    }
+}
+
+
 impl Province {
     pub fn index(&self) -> u16 {
         self.0
     }
 
-    fn name(&self) -> &'static str {
-        PROVINCES_DB[self.0 as usize]
+    pub fn name<'a>(&self, db: &'a IpDb) -> Option<&'a str> {
+        db.provinces().get(self.0 as usize)
     }
-}
 
-impl Into<u16> for Province {
-    fn into(self) -> u16 {
-        self.0
+    pub fn country(&self, db: &IpDb) -> Option<Country> {
+        db.province_parent(self.0 as usize).and_then(country)
     }
-}
 
-impl FromStr for Province {
-    type Err = ();
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        PROVINCES_DB
-            .binary_search(&s)
+    /// Province names are unique within a country, e.g. `Georgia` in the US.
+    pub fn find(db: &IpDb, country: Country, name: &str) -> Option<Self> {
+        let provinces = db.provinces();
+        let key = Some(country.index());
+        let start = partition_point(0..provinces.len(), |idx| db.province_parent(idx) < key);
+        let end = partition_point(start..provinces.len(), |idx| db.province_parent(idx) <= key);
+
+        provinces.binary_search_range(start..end, name)
             .map(|idx| Province(idx as u16))
-            .map_err(|_| ())
+    }
+}
+
+impl From<Province> for u16 {
+    fn from(province: Province) -> u16 {
+        province.0
     }
 }
 
 impl fmt::Debug for Province {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-       write!(f, "{:?}", self.name())
+       write!(f, "Province({})", self.0)
     }
 }
 
@@ -99,29 +169,90 @@ impl City {
         self.0
     }
 
-    fn name(&self) -> &'static str {
-        CITIES_DB[self.0 as usize]
+    pub fn name<'a>(&self, db: &'a IpDb) -> Option<&'a str> {
+        db.cities().get(self.0 as usize)
     }
-}
 
-impl Into<u32> for City {
-    fn into(self) -> u32 {
-        self.0
+    pub fn province(&self, db: &IpDb) -> Option<Province> {
+        let (_, province) = db.city_parent(self.0 as usize)?;
+        if province == u16::MAX {
+            None
+        } else {
+            Some(Province(province))
+        }
     }
-}
 
-impl FromStr for City {
-    type Err = ();
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        CITIES_DB
-            .binary_search(&s)
+    pub fn country(&self, db: &IpDb) -> Option<Country> {
+        db.city_parent(self.0 as usize).and_then(|(country_index, _)| country(country_index))
+    }
+
+    /// Every city called `name` in `country`, at most one per province,
+    /// e.g. the many `Springfield`s in the US.
+    pub fn find(db: &IpDb, country: Country, name: &str) -> Vec<Self> {
+        let cities = db.cities();
+        let key = Some(country.index());
+        let country_of = |idx: usize| db.city_parent(idx).map(|(country_index, _)| country_index);
+        let mut start = partition_point(0..cities.len(), |idx| country_of(idx) < key);
+        let end = partition_point(start..cities.len(), |idx| country_of(idx) <= key);
+
+        let mut found = Vec::new();
+        while start < end {
+            let parent = db.city_parent(start);
+            let group_end = partition_point(start..end, |idx| db.city_parent(idx) <= parent);
+            if let Some(idx) = cities.binary_search_range(start..group_end, name) {
+                found.push(City(idx as u32));
+            }
+            start = group_end;
+        }
+
+        found
+    }
+
+    /// The city called `name` in `province`.
+    pub fn find_in(db: &IpDb, province: Province, name: &str) -> Option<Self> {
+        let cities = db.cities();
+        let key = Some((db.province_parent(province.0 as usize)?, province.0));
+        let start = partition_point(0..cities.len(), |idx| db.city_parent(idx) < key);
+        let end = partition_point(start..cities.len(), |idx| db.city_parent(idx) <= key);
+
+        cities.binary_search_range(start..end, name)
             .map(|idx| City(idx as u32))
-            .map_err(|_| ())
+    }
+}
+
+impl From<City> for u32 {
+    fn from(city: City) -> u32 {
+        city.0
     }
 }
 
 impl fmt::Debug for City {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-       write!(f, "{:?}", self.name())
+       write!(f, "City({})", self.0)
     }
 }
+
+fn country(idx: u8) -> Option<Country> {
+    if (idx as usize) < COUNTRY_CODES_LEN {
+        Some(Country::from_index(idx))
+    } else {
+        None
+    }
+}
+
+/// First index in `range` where `pred` is false, `pred` must hold for a
+/// prefix of `range` only.
+fn partition_point<F: Fn(usize) -> bool>(range: Range<usize>, pred: F) -> usize {
+    let mut lo = range.start;
+    let mut hi = range.end;
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if pred(mid) {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+
+    lo
+}
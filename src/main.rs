@@ -1,302 +1,617 @@
 use std::env;
-use std::ops::Range;
-use std::cmp::Ordering;
-use std::marker::PhantomData;
+use std::fs::{ self, File };
+use std::net::IpAddr;
+use std::io::{ BufWriter, Write };
+
+use ip2location::{ IpDb, Zone, Normalization, Builder, DropReason, Country, Province, City, Place, CidrSet, Special };
+use ip2location::export::{ self, IpRoute, ClashBehavior };
+
+
+#[cfg(not(feature = "embedded"))]
+const DEFAULT_DB_PATH: &str = "ip_db.bin";
+const DEFAULT_OUTPUT_PATH: &str = "ip_db.bin";
+
+/// Prints the help and exits, `code` is 0 when the help was asked for and 2
+/// for bad arguments. Failures after the arguments were accepted, such as
+/// unreadable files or bad data, exit with 1.
+fn usage(code: i32) -> ! {
+    let text = "
+    Usage:
+        ip2location [--db <path>] [--mmap] [--validate] [--no-normalize] <ip>
+        ip2location build [--v4 <csv>] [--v6 <csv>] [-o <path>] [--dataset <name>] [--max-malformed <n>]
+                          [--country-only] [--compact] [--eytzinger] [--prefix-index]
+        ip2location ranges [--db <path>] [--mmap] [--family v4|v6] <place>...
+        ip2location cidr [--db <path>] [--mmap] [--family v4|v6] <place>...
+        ip2location routes [--db <path>] [--mmap] [--family v4|v6] <place>... [--exclude <cidr>]...
+                           [--format ip-route|wireguard] [--dev <name>] [--table <id>] [-o <path>]
+        ip2location firewall [--db <path>] [--mmap] [--family v4|v6] (--country <CC>)...
+                             [--format nft|ipset] [--table <name>] [-o <path>]
+        ip2location pac [--db <path>] [--mmap] [--family v4|v6] <place>... [--direct <cidr>]...
+                        [--proxy <spec>] [-o <path>]
+        ip2location rules [--db <path>] [--mmap] [--family v4|v6] <place>...
+                          [--format clash|clash-classical|srs|geoip-dat] [-o <path>]
+
+    Place:
+        --country <CC> | --province <CC>/<name> | --city <CC>/[<province>/]<name>
 
-mod country;
-mod location;
-mod location_db;
-
-pub use country::Country;
-pub use location::{Location, Province, City};
-pub use location_db::{ PROVINCES_DB, CITIES_DB };
+    Example:
+        $ ip2location 8.8.8.8
+        $ ip2location --db /var/lib/ip2location/ip_db.bin 8.8.8.8
+        $ ip2location build --v4 data/IP2LOCATION-LITE-DB3.CSV \\
+                            --v6 data/IP2LOCATION-LITE-DB3.IPV6.CSV -o ip_db.bin
+        $ ip2location ranges --city US/Springfield --family v4
+        $ ip2location cidr --country CN --family v4
+        $ ip2location routes --country CN --exclude 203.0.113.0/24 --format wireguard
+        $ ip2location firewall --country CN --country RU --format nft -o geoip.nft
+        $ ip2location pac --country CN --direct 192.168.0.0/16 --proxy \"SOCKS5 127.0.0.1:1080\" -o proxy.pac
+        $ ip2location rules --country CN --format srs -o geoip-cn.srs
+    ";
+    if code == 0 {
+        println!("{}", text);
+    } else {
+        eprintln!("{}", text);
+    }
+    std::process::exit(code);
+}
 
+fn open_db(path: Option<String>, mmap: bool) -> IpDb {
+    #[cfg(not(feature = "mmap"))]
+    let _ = mmap;
+
+    let res = match path {
+        #[cfg(feature = "mmap")]
+        Some(path) if mmap => IpDb::open_mmap(path),
+        Some(path) => IpDb::open(path),
+        #[cfg(feature = "embedded")]
+        None => IpDb::embedded(),
+        #[cfg(all(not(feature = "embedded"), feature = "mmap"))]
+        None if mmap => IpDb::open_mmap(DEFAULT_DB_PATH),
+        #[cfg(not(feature = "embedded"))]
+        None => IpDb::open(DEFAULT_DB_PATH),
+    };
+
+    match res {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Failed to load database: {}", e);
+            std::process::exit(1);
+        },
+    }
+}
 
-pub static IP_DB: &'static [u8] = include_bytes!("ip_db.bin");
+fn build<I: Iterator<Item = String>>(mut args: I) {
+    let mut v4_paths = Vec::new();
+    let mut v6_paths = Vec::new();
+    let mut output = DEFAULT_OUTPUT_PATH.to_string();
+    let mut dataset = None;
+    let mut max_malformed = 0usize;
+    let mut country_only = false;
+    let mut compact = false;
+    let mut eytzinger = false;
+    let mut prefix_index = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => usage(0),
+            "--country-only" => {
+                country_only = true;
+                continue;
+            },
+            "--compact" => {
+                compact = true;
+                continue;
+            },
+            "--eytzinger" => {
+                eytzinger = true;
+                continue;
+            },
+            "--prefix-index" => {
+                prefix_index = true;
+                continue;
+            },
+            _ => (),
+        }
+        let value = match args.next() {
+            Some(value) => value,
+            None => usage(2),
+        };
+        match arg.as_str() {
+            "--v4" => v4_paths.push(value),
+            "--v6" => v6_paths.push(value),
+            "-o" | "--output" => output = value,
+            "--dataset" => dataset = Some(value),
+            "--max-malformed" => match value.parse::<usize>() {
+                Ok(n) => max_malformed = n,
+                Err(_) => usage(2),
+            },
+            _ => usage(2),
+        }
+    }
+    if v4_paths.is_empty() && v6_paths.is_empty() {
+        usage(2);
+    }
 
-const V4_RECORD_SIZE: usize = std::mem::size_of::<Record<u32>>();
-const V6_RECORD_SIZE: usize = std::mem::size_of::<Record<u128>>();
+    let exit = |e: std::io::Error| -> ! {
+        eprintln!("Failed to build database: {}", e);
+        std::process::exit(1);
+    };
 
-pub struct Record<T: Sized> {
-    pub start: T,
-    pub end: T,
-    pub location_id: u64,
-}
+    let mut builder = Builder::new();
+    if let Some(dataset) = dataset {
+        builder.set_dataset(&dataset);
+    }
+    builder.set_country_only(country_only);
+    builder.set_compact(compact);
+    builder.set_eytzinger(eytzinger);
+    builder.set_prefix_index(prefix_index);
+    for path in v4_paths.iter() {
+        builder.set_source(path);
+        File::open(path).and_then(|file| builder.add_v4(file)).unwrap_or_else(|e| exit(e));
+    }
+    for path in v6_paths.iter() {
+        builder.set_source(path);
+        File::open(path).and_then(|file| builder.add_v6(file)).unwrap_or_else(|e| exit(e));
+    }
 
-impl Record<u32> {
-    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() < V4_RECORD_SIZE {
-            return None;
-        }
-        let start = u32::from_le_bytes([
-            bytes[0], bytes[1], bytes[2], bytes[3]
-        ]);
-        let end = u32::from_le_bytes([
-            bytes[4], bytes[5], bytes[6], bytes[7]
-        ]);
-        let location_id = u64::from_le_bytes([
-            bytes[8], bytes[9], bytes[10], bytes[11],
-            bytes[12], bytes[13], bytes[14], bytes[15],
-        ]);
-
-        Some(Record { start, end, location_id })
+    let malformed = builder.report().malformed();
+    if malformed > max_malformed {
+        print_report(&builder);
+        eprintln!("Too many malformed rows: {} (max {})", malformed, max_malformed);
+        std::process::exit(1);
     }
-}
 
-impl Record<u128> {
-    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() < V6_RECORD_SIZE {
-            return None;
-        }
-        let start = u128::from_le_bytes([
-            bytes[0], bytes[1], bytes[2], bytes[3],
-            bytes[4], bytes[5], bytes[6], bytes[7],
-            bytes[8], bytes[9], bytes[10], bytes[11],
-            bytes[12], bytes[13], bytes[14], bytes[15],
-        ]);
-        let end = u128::from_le_bytes([
-            bytes[16], bytes[17], bytes[18], bytes[19],
-            bytes[20], bytes[21], bytes[22], bytes[23],
-            bytes[24], bytes[25], bytes[26], bytes[27],
-            bytes[28], bytes[29], bytes[30], bytes[31],
-        ]);
-        let location_id = u64::from_le_bytes([
-            bytes[32], bytes[33], bytes[34], bytes[35],
-            bytes[36], bytes[37], bytes[38], bytes[39],
-        ]);
-        
-        Some(Record { start, end, location_id })
+    // NOTE: 运行中的进程可能 mmap 了旧文件，原地改写会让它们 SIGBUS，
+    //       先写到同目录的临时文件再 rename 过去。
+    let tmp = temp_path(&output);
+    let res = File::create(&tmp).and_then(|file| {
+        let mut w = BufWriter::new(file);
+        builder.write(&mut w)?;
+        w.into_inner().map_err(|e| e.into_error())?.sync_all()
+    }).and_then(|_| fs::rename(&tmp, &output));
+    if let Err(e) = res {
+        let _ = fs::remove_file(&tmp);
+        exit(e);
     }
-}
 
-struct IpDb {
-    v4_records_range: Range<usize>,
-    v4_records_len: usize,
-    v4_record_size: usize,
+    print_report(&builder);
+    println!("Output: {}", output);
+}
 
-    v6_records_range: Range<usize>,
-    v6_records_len: usize,
-    v6_record_size: usize,
+/// A temporary file next to `path`, unique to this process so that
+/// concurrent writers to the same output do not clobber each other.
+fn temp_path(path: &str) -> String {
+    format!("{}.{}.tmp", path, std::process::id())
 }
 
-impl IpDb {
-    pub fn new() -> Self {
-        let v4_db_data_zone_start = u32::from_le_bytes([ IP_DB[0], IP_DB[1], IP_DB[2], IP_DB[3] ]) as usize;
-        let v4_db_data_zone_end   = u32::from_le_bytes([ IP_DB[4], IP_DB[5], IP_DB[6], IP_DB[7] ]) as usize;
-        let v6_db_data_zone_start = u32::from_le_bytes([ IP_DB[8], IP_DB[9], IP_DB[10], IP_DB[11] ]) as usize;
-        let v6_db_data_zone_end   = u32::from_le_bytes([ IP_DB[12], IP_DB[13], IP_DB[14], IP_DB[15] ]) as usize;
+/// Options shared by the subcommands that select places.
+struct PlaceArgs {
+    db: IpDb,
+    family: Option<String>,
+    places: Vec<Place>,
+    /// Subcommand specific options, only the names passed to `parse`.
+    options: Vec<(String, String)>,
+}
 
-        let v4_record_size   = std::mem::size_of::<Record<u32>>();
-        let v4_records_range = v4_db_data_zone_start .. v4_db_data_zone_end;
-        let v4_records_len   = (v4_db_data_zone_end - v4_db_data_zone_start) / v4_record_size;
+impl PlaceArgs {
+    /// `countries_only` rejects `--province` and `--city` as argument errors,
+    /// for the subcommands that write one set per country.
+    fn parse<I: Iterator<Item = String>>(mut args: I, extra: &[&str], countries_only: bool) -> Self {
+        let mut db_path = None;
+        let mut mmap = false;
+        let mut family = None;
+        let mut names = Vec::new();
+        let mut options = Vec::new();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => usage(0),
+                "--mmap" => {
+                    mmap = true;
+                    continue;
+                },
+                _ => (),
+            }
+            let value = match args.next() {
+                Some(value) => value,
+                None => usage(2),
+            };
+            match arg.as_str() {
+                "--db" => db_path = Some(value),
+                "--family" if value == "v4" || value == "v6" => family = Some(value),
+                "--province" | "--city" if countries_only => {
+                    eprintln!("Sets are per country, use --country");
+                    std::process::exit(2);
+                },
+                "--country" | "--province" | "--city" => names.push((arg, value)),
+                _ if extra.contains(&arg.as_str()) => options.push((arg, value)),
+                _ => usage(2),
+            }
+        }
+        if names.is_empty() {
+            usage(2);
+        }
 
-        let v6_record_size   = std::mem::size_of::<Record<u128>>();
-        let v6_records_range = v6_db_data_zone_start .. v6_db_data_zone_end;
-        let v6_records_len   = (v6_db_data_zone_end - v6_db_data_zone_start) / v6_record_size;
+        let db = open_db(db_path, mmap);
+        let mut places = Vec::new();
+        for (kind, name) in names.iter() {
+            let found = find_places(&db, kind, name);
+            if found.is_empty() {
+                eprintln!("Unknown place: {}", name);
+                std::process::exit(1);
+            }
+            places.extend(found);
+        }
 
-        Self { v4_record_size, v4_records_range, v4_records_len, v6_record_size, v6_records_range, v6_records_len }
+        Self { db, family, places, options }
     }
 
-    pub fn query(&self, addr: &std::net::IpAddr) -> Option<Location> {
-        match addr {
-            std::net::IpAddr::V4(v4_addr) => {
-                let v4_number = u32::from(*v4_addr);
-                let records = V4Records { range: self.v4_records_range.clone(), len: self.v4_records_len };
-                records.binary_search(v4_number)
-                    .map(|record| Location(record.location_id) )
-            },
-            std::net::IpAddr::V6(v6_addr) => {
-                let v6_number = u128::from(*v6_addr);
-                let records = V6Records { range: self.v6_records_range.clone(), len: self.v6_records_len };
-                records.binary_search(v6_number)
-                    .map(|record| Location(record.location_id) )
-            },
-        }
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(arg, _)| arg == name).map(|(_, value)| value.as_str())
     }
-}
 
-struct V6Records {
-    range: Range<usize>,
-    len: usize,
-}
+    fn options<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.options.iter().filter(move |(arg, _)| arg == name).map(|(_, value)| value.as_str())
+    }
 
-struct V4Records {
-    range: Range<usize>,
-    len: usize,
-}
+    /// Prefixes of the selected places, limited to `--family`.
+    fn cidr_set(&self) -> CidrSet {
+        let mut set = self.db.cidr_set(&self.places);
+        self.filter_family(&mut set);
+        set
+    }
 
-impl V4Records {
-    pub fn len(&self) -> usize {
-        self.len
+    /// One set per `--country`, named by the lower case country code.
+    fn country_sets(&self) -> Vec<(String, CidrSet)> {
+        let mut sets = Vec::new();
+        for place in self.places.iter() {
+            let country = match place {
+                Place::Country(country) => country,
+                _ => {
+                    eprintln!("Sets are per country, use --country");
+                    std::process::exit(2);
+                },
+            };
+            let mut set = self.db.cidr_set(&[*place]);
+            self.filter_family(&mut set);
+            sets.push((country.code().to_lowercase(), set));
+        }
+        sets
     }
 
-    pub fn get(&self, idx: usize) -> Option<Record<u32>> {
-        if idx >= self.len {
-            return None;
+    fn filter_family(&self, set: &mut CidrSet) {
+        if !self.v4() {
+            set.v4.clear();
         }
-        
-        let offset = idx * V4_RECORD_SIZE;
-        if offset + V4_RECORD_SIZE > self.range.end {
-            return None;
+        if !self.v6() {
+            set.v6.clear();
         }
+    }
 
-        let bytes = &IP_DB[offset..offset+V4_RECORD_SIZE];
-        
-        Record::<u32>::from_bytes(bytes)
+    fn v4(&self) -> bool {
+        self.family.as_deref() != Some("v6")
     }
 
-    pub unsafe fn get_unchecked(&self, idx: usize) -> Record<u32> {
-        unimplemented!()
+    fn v6(&self) -> bool {
+        self.family.as_deref() != Some("v4")
     }
+}
 
-    pub fn binary_search(&self, x: u32) -> Option<Record<u32>> {
-        let s = self;
+fn ranges<I: Iterator<Item = String>>(args: I) {
+    let args = PlaceArgs::parse(args, &[], false);
+    let mut ranges = args.places.iter()
+        .flat_map(|place| args.db.ranges(*place))
+        .filter(|(start, _)| if start.is_ipv4() { args.v4() } else { args.v6() })
+        .collect::<Vec<_>>();
+    // NOTE: 记录的区间互不重叠，重叠的地点 (如国家和它的省份) 只会得到完全相同的区间。
+    ranges.sort();
+    ranges.dedup();
+    for (start, end) in ranges.iter() {
+        println!("{}-{}", start, end);
+    }
+}
 
-        let mut size = s.len();
-        if size == 0 {
-            return None;
-        }
+fn cidr<I: Iterator<Item = String>>(args: I) {
+    let args = PlaceArgs::parse(args, &[], false);
+    let set = args.cidr_set();
+    for cidr in set.v4.iter() {
+        println!("{}", cidr);
+    }
+    for cidr in set.v6.iter() {
+        println!("{}", cidr);
+    }
+}
 
-        let mut base = 0usize;
-        while size > 1 {
-            let half = size / 2;
-            let mid = base + half;
-            // mid is always in [0, size), that means mid is >= 0 and < size.
-            // mid >= 0: by definition
-            // mid < size: mid = size / 2 + size / 4 + size / 8 ...
-            // let cmp = f(unsafe { s.get_unchecked(mid) });
-            let item = s.get(mid).unwrap();
-            let cmp = {
-                if x >= item.start && x <= item.end {
-                    Ordering::Equal
-                } else if x > item.end {
-                    Ordering::Less
-                } else if x < item.start {
-                    Ordering::Greater
-                } else {
-                    unreachable!()
-                }
+/// Output of `routes`.
+enum RouteFormat {
+    IpRoute(IpRoute),
+    WireGuard,
+}
+
+fn routes<I: Iterator<Item = String>>(args: I) {
+    let args = PlaceArgs::parse(args, &["--exclude", "--format", "--dev", "--table", "-o"], false);
+    // NOTE: 先解析完所有选项再打开输出文件，参数错误时不能截断已有的文件。
+    let format = match args.option("--format").unwrap_or("ip-route") {
+        "ip-route" => {
+            let mut route = IpRoute::new(args.option("--dev").unwrap_or("wg0"));
+            route.table = match args.option("--table").map(|table| table.parse::<u32>()) {
+                Some(Ok(table)) => Some(table),
+                Some(Err(_)) => usage(2),
+                None => None,
             };
-            base = if cmp == Ordering::Greater { base } else { mid };
-            size -= half;
+            RouteFormat::IpRoute(route)
+        },
+        "wireguard" => RouteFormat::WireGuard,
+        _ => usage(2),
+    };
+
+    // NOTE: 局域网、回环、链路本地和组播等地址永远不走隧道。
+    let mut local = args.db.cidr_set(&args.places).union(&Special::non_global());
+    // NOTE: 2000::/3 以外的 IPv6 地址没有分配给公网，整块留在本地，
+    //       否则 ::/128、100::/64 这些洞会把路由拆成上百条。
+    for cidr in ["::/3", "4000::/2", "8000::/1"].iter() {
+        local.insert(cidr);
+    }
+    for cidr in args.options("--exclude") {
+        if !local.insert(cidr) {
+            eprintln!("Invalid prefix: {}", cidr);
+            std::process::exit(1);
         }
-        // base is always in [0, size) because base <= mid.
-        // let cmp = f(unsafe { s.get_unchecked(base) });
-        let item = s.get(base).unwrap();
-        let cmp = {
-            if x >= item.start && x <= item.end {
-                return Some(item);
-            } else if x > item.end {
-                return None;
-            } else if x < item.start {
-                return None;
-            } else {
-                unreachable!()
-            }
-        };
     }
+    let mut tunnel = local.complement();
+    args.filter_family(&mut tunnel);
+
+    write_output(args.option("-o"), |w| match format {
+        RouteFormat::IpRoute(ref route) => export::write_ip_route(w, &tunnel, route),
+        RouteFormat::WireGuard => export::write_wireguard(w, &tunnel),
+    });
 }
 
-impl V6Records {
-    pub fn len(&self) -> usize {
-        self.len
-    }
+/// Output of `firewall`.
+enum FirewallFormat {
+    Nft,
+    Ipset,
+}
 
-    pub fn get(&self, idx: usize) -> Option<Record<u128>> {
-        if idx >= self.len {
-            return None;
-        }
-        
-        let offset = idx * V6_RECORD_SIZE;
-        if offset + V6_RECORD_SIZE > self.range.end {
-            return None;
-        }
+fn firewall<I: Iterator<Item = String>>(args: I) {
+    let args = PlaceArgs::parse(args, &["--format", "--table", "-o"], true);
+    let format = match args.option("--format").unwrap_or("nft") {
+        "nft" => FirewallFormat::Nft,
+        "ipset" => FirewallFormat::Ipset,
+        _ => usage(2),
+    };
+    let sets = args.country_sets();
+    write_output(args.option("-o"), |w| match format {
+        FirewallFormat::Nft => export::write_nftables(w, args.option("--table").unwrap_or("geoip"), &sets),
+        FirewallFormat::Ipset => export::write_ipset(w, &sets, args.v4(), args.v6()),
+    });
+}
 
-        let bytes = &IP_DB[offset..offset+V6_RECORD_SIZE];
-        
-        Record::<u128>::from_bytes(bytes)
+fn pac<I: Iterator<Item = String>>(args: I) {
+    let args = PlaceArgs::parse(args, &["--direct", "--proxy", "-o"], false);
+    let mut direct = args.cidr_set();
+    for cidr in args.options("--direct") {
+        if !direct.insert(cidr) {
+            eprintln!("Invalid prefix: {}", cidr);
+            std::process::exit(1);
+        }
     }
 
-    pub unsafe fn get_unchecked(&self, idx: usize) -> Record<u128> {
-        unimplemented!()
-    }
+    let proxy = args.option("--proxy").unwrap_or("SOCKS5 127.0.0.1:1080; DIRECT");
+    write_output(args.option("-o"), |w| export::write_pac(w, &direct, proxy));
+}
 
-    pub fn binary_search(&self, x: u128) -> Option<Record<u128>> {
-        let s = self;
+/// Output of `rules`.
+enum RulesFormat {
+    Clash(ClashBehavior),
+    Srs,
+    GeoipDat,
+}
 
-        let mut size = s.len();
-        if size == 0 {
-            return None;
-        }
+fn rules<I: Iterator<Item = String>>(args: I) {
+    let args = PlaceArgs::parse(args, &["--format", "-o"], false);
+    let format = match args.option("--format").unwrap_or("clash") {
+        "clash" => RulesFormat::Clash(ClashBehavior::IpCidr),
+        "clash-classical" => RulesFormat::Clash(ClashBehavior::Classical),
+        "srs" => RulesFormat::Srs,
+        "geoip-dat" => RulesFormat::GeoipDat,
+        _ => usage(2),
+    };
+    match format {
+        RulesFormat::Clash(behavior) => {
+            let set = args.cidr_set();
+            write_output(args.option("-o"), |w| export::write_clash(w, &set, behavior));
+        },
+        RulesFormat::Srs => {
+            let set = args.cidr_set();
+            write_output(args.option("-o"), |w| export::write_srs(w, &set));
+        },
+        RulesFormat::GeoipDat => {
+            let sets = args.country_sets();
+            write_output(args.option("-o"), |w| export::write_geoip_dat(w, &sets));
+        },
+    }
+}
 
-        let mut base = 0usize;
-        while size > 1 {
-            let half = size / 2;
-            let mid = base + half;
-            // mid is always in [0, size), that means mid is >= 0 and < size.
-            // mid >= 0: by definition
-            // mid < size: mid = size / 2 + size / 4 + size / 8 ...
-            // let cmp = f(unsafe { s.get_unchecked(mid) });
-            let item = s.get(mid).unwrap();
-            let cmp = {
-                if x >= item.start && x <= item.end {
-                    Ordering::Equal
-                } else if x > item.end {
-                    Ordering::Less
-                } else if x < item.start {
-                    Ordering::Greater
-                } else {
-                    unreachable!()
-                }
-            };
-            base = if cmp == Ordering::Greater { base } else { mid };
-            size -= half;
-        }
-        // base is always in [0, size) because base <= mid.
-        // let cmp = f(unsafe { s.get_unchecked(base) });
-        let item = s.get(base).unwrap();
-        let cmp = {
-            if x >= item.start && x <= item.end {
-                return Some(item);
-            } else if x > item.end {
-                return None;
-            } else if x < item.start {
-                return None;
-            } else {
-                unreachable!()
+/// Writes to `path`, or stdout if it is `None`. The file is written next to
+/// `path` first and only renamed over it once `f` succeeded.
+fn write_output<F: FnOnce(&mut dyn Write) -> std::io::Result<()>>(path: Option<&str>, f: F) {
+    let res = match path {
+        Some(path) => {
+            let tmp = temp_path(path);
+            let res = File::create(&tmp).and_then(|file| {
+                let mut w = BufWriter::new(file);
+                f(&mut w)?;
+                w.flush()
+            }).and_then(|_| fs::rename(&tmp, path));
+            if res.is_err() {
+                let _ = fs::remove_file(&tmp);
             }
-        };
+            res
+        },
+        None => {
+            let stdout = std::io::stdout();
+            let mut w = stdout.lock();
+            f(&mut w).and_then(|_| w.flush())
+        },
+    };
+    if let Err(e) = res {
+        eprintln!("Failed to write output: {}", e);
+        std::process::exit(1);
     }
 }
 
-pub fn query(addr: &std::net::IpAddr) -> Option<Location> {
-    let db = IpDb::new();
-    db.query(addr)
+/// `--city US/Springfield` matches every Springfield in the US,
+/// `--city US/Illinois/Springfield` only one.
+fn find_places(db: &IpDb, kind: &str, name: &str) -> Vec<Place> {
+    let mut parts = name.splitn(3, '/');
+    let country = match parts.next().and_then(|code| code.parse::<Country>().ok()) {
+        Some(country) => country,
+        None => return Vec::new(),
+    };
+    let rest = parts.collect::<Vec<_>>();
+    match (kind, rest.as_slice()) {
+        ("--country", []) => vec![Place::Country(country)],
+        ("--province", [province]) => Province::find(db, country, province).into_iter().map(Place::from).collect(),
+        ("--city", [city]) => City::find(db, country, city).into_iter().map(Place::from).collect(),
+        ("--city", [province, city]) => Province::find(db, country, province)
+            .and_then(|province| City::find_in(db, province, city))
+            .into_iter()
+            .map(Place::from)
+            .collect(),
+        _ => Vec::new(),
+    }
 }
 
-fn usage() {
-    println!("
-    Example:
-        $ ip2location 8.8.8.8
-    ");
-    std::process::exit(0);
+fn print_report(builder: &Builder) {
+    let report = builder.report();
+    if let Some(tier) = builder.tier() {
+        println!("Tier:                {:?}", tier);
+    }
+    println!("Rows read:           {}", report.rows_read);
+    println!("Dropped, malformed:  {}", report.dropped(DropReason::Malformed));
+    println!("Dropped, bad addr:   {}", report.dropped(DropReason::InvalidAddress));
+    println!("Dropped, bad range:  {}", report.dropped(DropReason::InvalidRange));
+    println!("Dropped, overlap:    {}", report.dropped(DropReason::OverlappingRange));
+    println!("Dropped, no country: {}", report.dropped(DropReason::UnknownCountry));
+    println!("IPv4 records:        {}", report.v4_records);
+    println!("IPv6 records:        {}", report.v6_records);
+    println!("Merged records:      {}", report.merged);
+    println!("Unique provinces:    {}", report.provinces);
+    println!("Unique cities:       {}", report.cities);
+    println!("Output size:         {} bytes", report.output_size);
+    for error in report.errors.iter() {
+        eprintln!("{}", error);
+    }
 }
 
 fn main() {
-    let mut args = env::args();
+    let mut args = env::args().peekable();
     args.next().unwrap();
 
-    if let Some(params) = args.next() {
-        match params.parse::<std::net::IpAddr>() {
-            Ok(addr) => {
-                let res = query(&addr);
-                println!("Query: {}  --> {:?}", addr, res);
+    // NOTE: 只有主动要帮助或者没有参数时才以 0 退出，参数错误一律是 2。
+    //       `-h` 只在选项名的位置上算帮助，`-o --help` 里它是选项的值。
+    if args.peek().is_none() {
+        usage(0);
+    }
+    match args.peek().map(|arg| arg.as_str()) {
+        Some("help") | Some("-h") | Some("--help") => usage(0),
+        Some("build") => {
+            args.next();
+            return build(args);
+        },
+        Some("ranges") => {
+            args.next();
+            return ranges(args);
+        },
+        Some("cidr") => {
+            args.next();
+            return cidr(args);
+        },
+        Some("routes") => {
+            args.next();
+            return routes(args);
+        },
+        Some("firewall") => {
+            args.next();
+            return firewall(args);
+        },
+        Some("pac") => {
+            args.next();
+            return pac(args);
+        },
+        Some("rules") => {
+            args.next();
+            return rules(args);
+        },
+        _ => (),
+    }
+
+    let mut db_path = None;
+    let mut mmap = false;
+    let mut validate = false;
+    let mut normalize = true;
+    let mut addr = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => usage(0),
+            "--mmap" => mmap = true,
+            "--validate" => validate = true,
+            "--no-normalize" => normalize = false,
+            "--db" => match args.next() {
+                Some(path) => db_path = Some(path),
+                None => usage(2),
             },
-            Err(_) => {
-                return usage();
+            _ => match arg.parse::<IpAddr>() {
+                Ok(v) => addr = Some(v),
+                Err(_) => usage(2),
             },
         }
-    } else {
-        return usage();
     }
-}
\ No newline at end of file
+
+    let addr = match addr {
+        Some(addr) => addr,
+        None => usage(2),
+    };
+
+    let mut db = open_db(db_path, mmap);
+    if !normalize {
+        db.set_normalization(Normalization::none());
+    }
+    if validate {
+        if let Err(e) = db.validate() {
+            eprintln!("Invalid database: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let result = db.lookup(&addr);
+    match result.location {
+        Some(location) => println!("Query: {}  --> {}", addr, location.display(&db)),
+        None => println!("Query: {}  --> None", addr),
+    }
+    if let Some(embedding) = result.embedding {
+        println!("Embedded: {} {}", embedding, result.addr);
+    }
+    if let (Some((start, end)), Some(index)) = (result.range, result.index) {
+        let zone = match result.zone {
+            Zone::V4 => "v4",
+            Zone::V6 => "v6",
+        };
+        println!("Range: {} - {}  ({} record #{})", start, end, zone, index);
+    }
+    if let Some((network, prefix_len)) = result.prefix() {
+        println!("Prefix: {}/{}", network, prefix_len);
+    }
+    if let Some(special) = result.special {
+        println!("Special: {}", special);
+    }
+    if let Some(details) = result.index.and_then(|index| db.details_at(result.zone, index)) {
+        if let Some(coordinates) = details.coordinates() {
+            println!("Coordinates: {}", coordinates);
+        }
+        if let Some(zip_code) = details.zip_code {
+            println!("ZIP code: {}", zip_code);
+        }
+        if let Some(time_zone) = details.time_zone {
+            println!("Time zone: UTC{}", time_zone);
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt;
+use std::net::IpAddr;
+
+use crate::address::Address;
+use crate::cidr::{ self, Cidr, CidrSet };
+
+
+/// An entry of the IANA IPv4 / IPv6 special-purpose address registries.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Special {
+    /// `0.0.0.0/32`, `::/128`
+    Unspecified,
+    /// "This network", the rest of `0.0.0.0/8`
+    ThisNetwork,
+    /// `127.0.0.0/8`, `::1/128`
+    Loopback,
+    /// RFC 1918, `10.0.0.0/8`, `172.16.0.0/12`, `192.168.0.0/16`
+    Private,
+    /// Carrier-grade NAT, `100.64.0.0/10`
+    SharedAddress,
+    /// `169.254.0.0/16`, `fe80::/10`
+    LinkLocal,
+    /// `224.0.0.0/4`, `ff00::/8`
+    Multicast,
+    /// `192.0.2.0/24`, `198.51.100.0/24`, `203.0.113.0/24`, `2001:db8::/32`, `3fff::/20`
+    Documentation,
+    /// `198.18.0.0/15`, `2001:2::/48`
+    Benchmarking,
+    /// `fc00::/7`
+    UniqueLocal,
+    /// `255.255.255.255/32`
+    Broadcast,
+    /// IPv4/IPv6 transition, NAT64 `64:ff9b::/96`, `64:ff9b:1::/48`,
+    /// IPv4-mapped `::ffff:0:0/96`, Teredo `2001::/32`, 6to4 `2002::/16`
+    /// and its relay anycast `192.88.99.0/24`
+    Transition,
+    /// Protocol assignments and reserved blocks, `192.0.0.0/24`, `240.0.0.0/4`,
+    /// `100::/64`, `2001::/23`, less the globally reachable assignments in them
+    Reserved,
+}
+
+impl fmt::Display for Special {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Special::Unspecified => "unspecified",
+            Special::ThisNetwork => "this network",
+            Special::Loopback => "loopback",
+            Special::Private => "private",
+            Special::SharedAddress => "shared address (CGNAT)",
+            Special::LinkLocal => "link-local",
+            Special::Multicast => "multicast",
+            Special::Documentation => "documentation",
+            Special::Benchmarking => "benchmarking",
+            Special::UniqueLocal => "unique local",
+            Special::Broadcast => "broadcast",
+            Special::Transition => "IPv4/IPv6 transition",
+            Special::Reserved => "reserved",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// NOTE: 按前缀从长到短排列，先匹配到的就是最具体的一项。
+const V4_SPECIAL: &[(Cidr<u32>, Special)] = &[
+    (Cidr { network: 0xffff_ffff, prefix_len: 32 }, Special::Broadcast),
+    (Cidr { network: 0x0000_0000, prefix_len: 32 }, Special::Unspecified),
+    (Cidr { network: 0xc000_0000, prefix_len: 24 }, Special::Reserved),
+    (Cidr { network: 0xc000_0200, prefix_len: 24 }, Special::Documentation),
+    (Cidr { network: 0xc058_6300, prefix_len: 24 }, Special::Transition),
+    (Cidr { network: 0xc633_6400, prefix_len: 24 }, Special::Documentation),
+    (Cidr { network: 0xcb00_7100, prefix_len: 24 }, Special::Documentation),
+    (Cidr { network: 0xa9fe_0000, prefix_len: 16 }, Special::LinkLocal),
+    (Cidr { network: 0xc0a8_0000, prefix_len: 16 }, Special::Private),
+    (Cidr { network: 0xc612_0000, prefix_len: 15 }, Special::Benchmarking),
+    (Cidr { network: 0xac10_0000, prefix_len: 12 }, Special::Private),
+    (Cidr { network: 0x6440_0000, prefix_len: 10 }, Special::SharedAddress),
+    (Cidr { network: 0x0000_0000, prefix_len: 8 }, Special::ThisNetwork),
+    (Cidr { network: 0x0a00_0000, prefix_len: 8 }, Special::Private),
+    (Cidr { network: 0x7f00_0000, prefix_len: 8 }, Special::Loopback),
+    (Cidr { network: 0xe000_0000, prefix_len: 4 }, Special::Multicast),
+    (Cidr { network: 0xf000_0000, prefix_len: 4 }, Special::Reserved),
+];
+
+const V6_SPECIAL: &[(Cidr<u128>, Special)] = &[
+    (Cidr { network: 0, prefix_len: 128 }, Special::Unspecified),
+    (Cidr { network: 1, prefix_len: 128 }, Special::Loopback),
+    (Cidr { network: 0x0064_ff9b << 96, prefix_len: 96 }, Special::Transition),
+    (Cidr { network: 0xffff << 32, prefix_len: 96 }, Special::Transition),
+    (Cidr { network: 0x0100_0000_0000_0000 << 64, prefix_len: 64 }, Special::Reserved),
+    (Cidr { network: 0x0064_ff9b_0001 << 80, prefix_len: 48 }, Special::Transition),
+    (Cidr { network: 0x2001_0002_0000 << 80, prefix_len: 48 }, Special::Benchmarking),
+    (Cidr { network: 0x2001_0000 << 96, prefix_len: 32 }, Special::Transition),
+    (Cidr { network: 0x2001_0db8 << 96, prefix_len: 32 }, Special::Documentation),
+    (Cidr { network: 0x2001 << 112, prefix_len: 23 }, Special::Reserved),
+    (Cidr { network: 0x3fff << 112, prefix_len: 20 }, Special::Documentation),
+    (Cidr { network: 0x2002 << 112, prefix_len: 16 }, Special::Transition),
+    (Cidr { network: 0xfe80 << 112, prefix_len: 10 }, Special::LinkLocal),
+    (Cidr { network: 0xff00 << 112, prefix_len: 8 }, Special::Multicast),
+    (Cidr { network: 0xfc00 << 112, prefix_len: 7 }, Special::UniqueLocal),
+];
+
+// NOTE: 注册表中标为 Globally Reachable 的子块，例如任播地址和 AMT，
+//       按普通公网地址处理。
+const V4_GLOBAL: &[Cidr<u32>] = &[
+    Cidr { network: 0xc000_0009, prefix_len: 32 },
+    Cidr { network: 0xc000_000a, prefix_len: 32 },
+];
+
+const V6_GLOBAL: &[Cidr<u128>] = &[
+    Cidr { network: (0x2001_0001 << 96) | 1, prefix_len: 128 },
+    Cidr { network: (0x2001_0001 << 96) | 2, prefix_len: 128 },
+    Cidr { network: (0x2001_0001 << 96) | 3, prefix_len: 128 },
+    Cidr { network: 0x2001_0004_0112 << 80, prefix_len: 48 },
+    Cidr { network: 0x2001_0003 << 96, prefix_len: 32 },
+    Cidr { network: 0x2001_0020 << 96, prefix_len: 28 },
+    Cidr { network: 0x2001_0030 << 96, prefix_len: 28 },
+];
+
+impl Special {
+    /// The special-purpose block `addr` belongs to, `None` for ordinary
+    /// (globally routed) addresses.
+    ///
+    /// IPv6 addresses embedding an IPv4 address are classified as they are,
+    /// `Transition` for the mapped, 6to4 and Teredo blocks. Normalize them
+    /// first to classify the embedded address.
+    pub fn classify(addr: &IpAddr) -> Option<Special> {
+        match addr {
+            IpAddr::V4(v4_addr) => find(V4_SPECIAL, V4_GLOBAL, u32::from(*v4_addr)),
+            IpAddr::V6(v6_addr) => find(V6_SPECIAL, V6_GLOBAL, u128::from(*v6_addr)),
+        }
+    }
+
+    /// The IPv4 addresses `classify` puts in this kind, as prefixes.
+    pub fn v4_cidrs(&self) -> Vec<Cidr<u32>> {
+        let special = *self;
+        cidr::to_cidrs(blocks(V4_SPECIAL, V4_GLOBAL, |s| s == special))
+    }
+
+    /// The IPv6 addresses `classify` puts in this kind, as prefixes.
+    pub fn v6_cidrs(&self) -> Vec<Cidr<u128>> {
+        let special = *self;
+        cidr::to_cidrs(blocks(V6_SPECIAL, V6_GLOBAL, |s| s == special))
+    }
+
+    /// Every block that is not routed on the public internet, that is every
+    /// kind but `Transition`, whose 6to4 and Teredo prefixes carry public
+    /// traffic, less the globally reachable assignments.
+    pub fn non_global() -> CidrSet {
+        CidrSet::from_ranges(
+            blocks(V4_SPECIAL, V4_GLOBAL, |special| special != Special::Transition),
+            blocks(V6_SPECIAL, V6_GLOBAL, |special| special != Special::Transition),
+        )
+    }
+}
+
+fn find<T: Address>(table: &[(Cidr<T>, Special)], global: &[Cidr<T>], addr: T) -> Option<Special> {
+    if global.iter().any(|cidr| cidr.contains(addr)) {
+        return None;
+    }
+    table.iter().find(|(cidr, _)| cidr.contains(addr)).map(|(_, special)| *special)
+}
+
+/// The ranges `find` maps to a kind accepted by `wanted`: each block less
+/// the more specific blocks listed before it and the `global` ones.
+fn blocks<T, F>(table: &[(Cidr<T>, Special)], global: &[Cidr<T>], wanted: F) -> Vec<(T, T)>
+    where T: Address,
+          F: Fn(Special) -> bool {
+    let mut claimed = global.iter().map(|cidr| (cidr.first(), cidr.last())).collect::<Vec<_>>();
+    let mut blocks = Vec::new();
+    for (cidr, special) in table.iter() {
+        let range = (cidr.first(), cidr.last());
+        if wanted(*special) {
+            blocks.extend(subtract(Some(range), claimed.iter().cloned()));
+        }
+        claimed.push(range);
+    }
+
+    cidr::merge(blocks)
+}
+
+fn subtract<T, A, B>(ranges: A, holes: B) -> Vec<(T, T)>
+    where T: Address,
+          A: IntoIterator<Item = (T, T)>,
+          B: IntoIterator<Item = (T, T)> {
+    cidr::complement(cidr::complement(ranges).into_iter().chain(holes))
+}
+
+
+#[test]
+fn test_classify() {
+    let classify = |s: &str| Special::classify(&s.parse().unwrap());
+
+    assert_eq!(classify("10.0.0.1"), Some(Special::Private));
+    assert_eq!(classify("172.31.255.255"), Some(Special::Private));
+    assert_eq!(classify("172.32.0.0"), None);
+    assert_eq!(classify("192.168.1.1"), Some(Special::Private));
+    assert_eq!(classify("100.64.0.1"), Some(Special::SharedAddress));
+    assert_eq!(classify("100.128.0.1"), None);
+    assert_eq!(classify("127.0.0.1"), Some(Special::Loopback));
+    assert_eq!(classify("169.254.1.1"), Some(Special::LinkLocal));
+    assert_eq!(classify("198.19.255.255"), Some(Special::Benchmarking));
+    assert_eq!(classify("203.0.113.7"), Some(Special::Documentation));
+    assert_eq!(classify("239.255.255.250"), Some(Special::Multicast));
+    assert_eq!(classify("255.255.255.255"), Some(Special::Broadcast));
+    assert_eq!(classify("250.0.0.1"), Some(Special::Reserved));
+    assert_eq!(classify("192.88.99.1"), Some(Special::Transition));
+    assert_eq!(classify("8.8.8.8"), None);
+    assert_eq!(classify("0.0.0.0"), Some(Special::Unspecified));
+    assert_eq!(classify("0.1.2.3"), Some(Special::ThisNetwork));
+
+    assert_eq!(classify("::"), Some(Special::Unspecified));
+    assert_eq!(classify("::1"), Some(Special::Loopback));
+    assert_eq!(classify("fe80::1"), Some(Special::LinkLocal));
+    assert_eq!(classify("fd12:3456::1"), Some(Special::UniqueLocal));
+    assert_eq!(classify("ff02::1"), Some(Special::Multicast));
+    assert_eq!(classify("2001:db8::1"), Some(Special::Documentation));
+    assert_eq!(classify("3fff:fff::1"), Some(Special::Documentation));
+    assert_eq!(classify("2001:2::1"), Some(Special::Benchmarking));
+    assert_eq!(classify("100::1"), Some(Special::Reserved));
+    assert_eq!(classify("64:ff9b::808:808"), Some(Special::Transition));
+    assert_eq!(classify("64:ff9b:1::1"), Some(Special::Transition));
+    assert_eq!(classify("64:ff9b:2::1"), None);
+    assert_eq!(classify("::ffff:1.2.3.4"), Some(Special::Transition));
+    assert_eq!(classify("2001:0:4136:e378:8000:63bf:3fff:fdd2"), Some(Special::Transition));
+    assert_eq!(classify("2001:1::1"), None);
+    assert_eq!(classify("2001:3::1"), None);
+    assert_eq!(classify("2001:4:112::1"), None);
+    assert_eq!(classify("2001:10::1"), Some(Special::Reserved));
+    assert_eq!(classify("192.0.0.9"), None);
+    assert_eq!(classify("192.0.0.8"), Some(Special::Reserved));
+    assert_eq!(classify("2001:200::1"), None);
+    assert_eq!(classify("2002:102:304::1"), Some(Special::Transition));
+    assert_eq!(classify("2001:4860:4860::8888"), None);
+
+    assert_eq!(Special::Private.v4_cidrs().iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+        vec!["10.0.0.0/8", "172.16.0.0/12", "192.168.0.0/16"]);
+    assert_eq!(Special::Transition.v6_cidrs().iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+        vec!["::ffff:0.0.0.0/96", "64:ff9b::/96", "64:ff9b:1::/48", "2001::/32", "2002::/16"]);
+    assert_eq!(Special::Private.v6_cidrs(), vec![]);
+    let reserved = Special::Reserved.v6_cidrs();
+    for addr in ["2001::1", "2001:2::1", "2001:3::1", "2001:4:112::1", "2001:20::1"].iter() {
+        let addr = u128::from(addr.parse::<std::net::Ipv6Addr>().unwrap());
+        assert!(!reserved.iter().any(|c| c.contains(addr)), "{:x}", addr);
+    }
+    assert!(reserved.iter().any(|c| c.contains(0x2001_0010 << 96)));
+    assert!(!Special::Reserved.v4_cidrs().iter().any(|c| c.contains(0xc000_0009)));
+
+    let non_global = Special::non_global();
+    let v4 = non_global.v4.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+    for cidr in ["0.0.0.0/8", "10.0.0.0/8", "127.0.0.0/8", "169.254.0.0/16", "192.168.0.0/16", "224.0.0.0/3"].iter() {
+        assert!(v4.contains(&cidr.to_string()), "{}", cidr);
+    }
+    assert!(!v4.contains(&"192.88.99.0/24".to_string()));
+    assert!(non_global.v6.iter().any(|c| c.contains(u128::from("fe80::1".parse::<std::net::Ipv6Addr>().unwrap()))));
+    assert!(!non_global.v6.iter().any(|c| c.contains(0x2002 << 112)));
+    assert!(!non_global.v6.iter().any(|c| c.contains(0x2001_0000_4136 << 80)));
+    assert!(!non_global.v6.iter().any(|c| c.contains(0x2001_0003 << 96)));
+    assert!(non_global.v6.iter().any(|c| c.contains(0x2001_0010 << 96)));
+    assert!(!non_global.v4.iter().any(|c| c.contains(0xc000_000a)));
+}
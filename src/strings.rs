@@ -0,0 +1,161 @@
+use std::io::{self, Write};
+use std::ops::Range;
+
+use crate::error::Error;
+
+
+// String Table (Little Endian)
+//
+// count    u32
+// offsets  [u32; count + 1]  relative to the start of `blob`
+// blob     [u8]              UTF-8, sorted, no separators
+//
+// Tables with a parent section (provinces, cities) are sorted by the parent
+// key first, so the same name may appear once per parent.
+#[derive(Clone, Copy)]
+pub struct StringTable<'a> {
+    offsets: &'a [u8],
+    blob: &'a [u8],
+    len: usize,
+}
+
+impl<'a> StringTable<'a> {
+    pub fn empty() -> Self {
+        Self { offsets: &[0, 0, 0, 0], blob: &[], len: 0 }
+    }
+
+    pub fn parse(bytes: &'a [u8], section: u32) -> Result<Self, Error> {
+        if bytes.len() < 4 {
+            return Err(Error::MisalignedZone(section));
+        }
+        let len = u32::from_le_bytes([ bytes[0], bytes[1], bytes[2], bytes[3] ]) as usize;
+        let offsets_end = len.checked_add(1)
+            .and_then(|n| n.checked_mul(4))
+            .and_then(|n| n.checked_add(4))
+            .ok_or(Error::MisalignedZone(section))?;
+        if bytes.len() < offsets_end {
+            return Err(Error::MisalignedZone(section));
+        }
+
+        let table = Self { offsets: &bytes[4..offsets_end], blob: &bytes[offsets_end..], len };
+        if table.offset(len) != table.blob.len() {
+            return Err(Error::MisalignedZone(section));
+        }
+
+        Ok(table)
+    }
+
+    #[inline]
+    fn offset(&self, idx: usize) -> usize {
+        let i = idx * 4;
+        u32::from_le_bytes([
+            self.offsets[i], self.offsets[i + 1], self.offsets[i + 2], self.offsets[i + 3]
+        ]) as usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&'a str> {
+        if idx >= self.len {
+            return None;
+        }
+        let start = self.offset(idx);
+        let end = self.offset(idx + 1);
+        let bytes = self.blob.get(start..end)?;
+
+        std::str::from_utf8(bytes).ok()
+    }
+
+    pub fn binary_search(&self, s: &str) -> Option<usize> {
+        self.binary_search_range(0..self.len, s)
+    }
+
+    /// Searches `range` only, which must be sorted by name, e.g. the
+    /// entries sharing one parent.
+    pub fn binary_search_range(&self, range: Range<usize>, s: &str) -> Option<usize> {
+        let mut lo = range.start;
+        let mut hi = range.end.min(self.len);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.get(mid)?.cmp(s) {
+                std::cmp::Ordering::Equal => return Some(mid),
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+            }
+        }
+
+        None
+    }
+
+    /// Checks that every entry is valid UTF-8 and the entries are sorted.
+    pub fn validate(&self, section: u32) -> Result<(), Error> {
+        self.validate_by_key(section, |_| ())
+    }
+
+    /// Like `validate`, but the entries are sorted by `(key(index), name)`.
+    pub fn validate_by_key<K: Ord, F: Fn(usize) -> K>(&self, section: u32, key: F) -> Result<(), Error> {
+        let mut prev: Option<(K, &str)> = None;
+        for idx in 0..self.len {
+            if self.offset(idx) > self.offset(idx + 1) {
+                return Err(Error::MisalignedZone(section));
+            }
+            let s = self.get(idx).ok_or(Error::InvalidString { section, index: idx })?;
+            let entry = (key(idx), s);
+            if let Some(prev) = prev {
+                if prev >= entry {
+                    return Err(Error::InvalidString { section, index: idx });
+                }
+            }
+            prev = Some(entry);
+        }
+
+        Ok(())
+    }
+}
+
+/// `strings` must already be sorted and deduplicated (per parent).
+pub fn write_table<W: Write, S: AsRef<str>>(w: &mut W, strings: &[S]) -> io::Result<()> {
+    w.write_all(&(strings.len() as u32).to_le_bytes())?;
+
+    let mut offset = 0u32;
+    w.write_all(&offset.to_le_bytes())?;
+    for s in strings.iter() {
+        offset += s.as_ref().len() as u32;
+        w.write_all(&offset.to_le_bytes())?;
+    }
+    for s in strings.iter() {
+        w.write_all(s.as_ref().as_bytes())?;
+    }
+
+    Ok(())
+}
+
+pub fn table_len<S: AsRef<str>>(strings: &[S]) -> usize {
+    4 + (strings.len() + 1) * 4 + strings.iter().map(|s| s.as_ref().len()).sum::<usize>()
+}
+
+
+#[test]
+fn test_string_table() {
+    let mut buf = Vec::new();
+    write_table(&mut buf, &["Beijing", "Guangdong", "Shanghai"]).unwrap();
+    assert_eq!(buf.len(), table_len(&["Beijing", "Guangdong", "Shanghai"]));
+
+    let table = StringTable::parse(&buf, 0).unwrap();
+    assert_eq!(table.len(), 3);
+    assert_eq!(table.get(1), Some("Guangdong"));
+    assert_eq!(table.get(3), None);
+    assert_eq!(table.binary_search("Shanghai"), Some(2));
+    assert_eq!(table.binary_search("Tianjin"), None);
+    assert_eq!(table.binary_search_range(0..2, "Shanghai"), None);
+    assert_eq!(table.binary_search_range(1..3, "Guangdong"), Some(1));
+    assert!(table.validate(0).is_ok());
+    assert!(table.validate_by_key(0, |idx| idx == 0).is_err());
+    assert!(table.validate_by_key(0, |idx| idx / 2).is_ok());
+}